@@ -0,0 +1,97 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Fields, Index};
+
+/// Implement `Reflect` for a struct whose fields are all `Reflect`. Tuple
+/// struct fields are named by their index, e.g. `0`.
+///
+/// ```ignore
+/// #[derive(Reflect)]
+/// struct Health { current: u32, max: u32 }
+/// ```
+#[proc_macro_derive(Reflect)]
+pub fn derive_reflect(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match reflect(&input) {
+        Ok(out) => out.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn reflect(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => return Err(syn::Error::new_spanned(&input.ident, "Reflect can only be derived for structs")),
+    };
+
+    // the name each field is looked up by, and how to access it
+    let (names, members): (Vec<String>, Vec<TokenStream2>) = match fields {
+        Fields::Named(named) => named.named.iter()
+            .map(|field| {
+                let ident = field.ident.as_ref().unwrap();
+                (ident.to_string(), quote!(#ident))
+            })
+            .unzip(),
+        Fields::Unnamed(unnamed) => (0..unnamed.unnamed.len())
+            .map(|i| {
+                let index = Index::from(i);
+                (i.to_string(), quote!(#index))
+            })
+            .unzip(),
+        Fields::Unit => (Vec::new(), Vec::new()),
+    };
+
+    let ident = &input.ident;
+    let reflect = quote!(::strata::Reflect);
+
+    // every type parameter has to be reflectable for its fields to be
+    let mut generics = input.generics.clone();
+    let params: Vec<_> = generics.type_params().map(|param| param.ident.clone()).collect();
+    let where_clause = generics.make_where_clause();
+    for param in params {
+        where_clause.predicates.push(parse_quote!(#param: #reflect));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics #reflect for #ident #ty_generics #where_clause {
+            fn type_name(&self) -> &'static str {
+                ::std::any::type_name::<Self>()
+            }
+
+            fn field_names(&self) -> &'static [&'static str] {
+                &[#(#names),*]
+            }
+
+            fn field(&self, name: &str) -> ::std::option::Option<&dyn #reflect> {
+                match name {
+                    #(#names => ::std::option::Option::Some(&self.#members),)*
+                    _ => ::std::option::Option::None,
+                }
+            }
+
+            fn field_mut(&mut self, name: &str) -> ::std::option::Option<&mut dyn #reflect> {
+                match name {
+                    #(#names => ::std::option::Option::Some(&mut self.#members),)*
+                    _ => ::std::option::Option::None,
+                }
+            }
+
+            fn describe(&self) -> ::std::string::String {
+                let fields: ::std::vec::Vec<::std::string::String> = ::std::vec![
+                    #(::std::format!("{}: {}", #names, #reflect::describe(&self.#members))),*
+                ];
+                ::std::format!("{} {{ {} }}", stringify!(#ident), fields.join(", "))
+            }
+
+            fn as_any(&self) -> &dyn ::std::any::Any {
+                self
+            }
+
+            fn as_any_mut(&mut self) -> &mut dyn ::std::any::Any {
+                self
+            }
+        }
+    })
+}
@@ -4,6 +4,7 @@ use std::ptr::NonNull;
 use std::alloc::Layout;
 use std::alloc::{alloc, realloc};
 use std::mem::needs_drop;
+use std::any::type_name;
 
 use strata_traits::Component;
 
@@ -17,6 +18,7 @@ pub struct AnonVec {
     len: usize,
     drop: Option<fn(*mut u8)>,
     cmpid: ComponentId,
+    name: &'static str,
 }
 
 impl AnonVec {
@@ -28,14 +30,61 @@ impl AnonVec {
             len: 1,
             drop: anon.drop,
             cmpid: anon.cmpid,
+            name: anon.name,
         }
     }
 
+    /// Create an empty vector for the same component as `anon`.
+    pub fn empty_for(anon: &Anon) -> Self {
+        Self {
+            inner: dangling(anon.layout),
+            layout: anon.layout,
+            capacity: 0,
+            len: 0,
+            drop: anon.drop,
+            cmpid: anon.cmpid,
+            name: anon.name,
+        }
+    }
+
+    /// Create an empty vector for the same component as this one.
+    pub fn empty_like(&self) -> Self {
+        Self {
+            inner: dangling(self.layout),
+            layout: self.layout,
+            capacity: 0,
+            len: 0,
+            drop: self.drop,
+            cmpid: self.cmpid,
+            name: self.name,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn id(&self) -> ComponentId {
+        self.cmpid
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    pub fn drop_fn(&self) -> Option<fn(*mut u8)> {
+        self.drop
+    }
+
     /// Append a value to the back of the vector. 
     pub fn push(&mut self, val: Anon) {
         unsafe {
             if val.cmpid != self.cmpid {
-                panic!("cmpids did not match!")
+                panic!("Attempted to push a {0} into a vector of {1}!", val.name, self.name)
             }
 
             // Allocate space as needed.
@@ -51,6 +100,57 @@ impl AnonVec {
         }
     }
 
+    /// Copy the value at `index` in `src` to the back of this vector.
+    /// The value in `src` is left as moved-from bits and must not be dropped.
+    pub fn push_from(&mut self, src: &AnonVec, index: usize) {
+        unsafe {
+            if src.cmpid != self.cmpid {
+                panic!("Attempted to push a {0} into a vector of {1}!", src.name, self.name)
+            }
+
+            if index >= src.len {
+                panic!("Index ({0}) must be less than the len! (len: {1})", index, src.len);
+            }
+
+            self.grow_if_full();
+
+            let size = self.layout.size();
+
+            ptr::copy_nonoverlapping(
+                src.inner.as_ptr().add(size * index), 
+                self.inner.as_ptr().add(size * self.len), 
+                size
+            );
+
+            self.len += 1;
+        }
+    }
+
+    /// Drop the value at `index` in place, leaving the slot as moved-from bits.
+    pub fn drop_at(&mut self, index: usize) {
+        if index >= self.len {
+            panic!("Index ({0}) must be less than the len! (len: {1})", index, self.len);
+        }
+
+        if let Some(drop) = self.drop {
+            unsafe { drop(self.inner.as_ptr().add(self.layout.size() * index)) }
+        }
+    }
+
+    /// Drop the value at `index` and overwrite it with `val`.
+    pub fn replace(&mut self, index: usize, val: Anon) {
+        if val.cmpid != self.cmpid {
+            panic!("Attempted to replace a {0} with a {1}!", self.name, val.name)
+        }
+
+        self.drop_at(index);
+
+        unsafe {
+            let size = self.layout.size();
+            ptr::copy(val.as_ptr(), self.inner.as_ptr().add(size * index), size);
+        }
+    }
+
     /// Returns a pointer to the value at Index.
     pub fn index(&self, index: usize) -> Anon {
         unsafe {
@@ -68,11 +168,37 @@ impl AnonVec {
                 inner: NonNull::new(src).unwrap(),
                 drop: self.drop,
                 cmpid: self.cmpid,
-                layout: self.layout
+                layout: self.layout,
+                name: self.name,
             }
         }
     }
 
+    pub fn get_as<T>(&self, index: usize) -> &T {
+        if index >= self.len {
+            panic!("Index ({0}) must be less than the len! (len: ({1})", index, self.len);
+        }
+
+        unsafe { &*(self.inner.as_ptr().add(self.layout.size() * index).cast::<T>()) }
+    }
+
+    pub fn get_mut_as<T>(&mut self, index: usize) -> &mut T {
+        if index >= self.len {
+            panic!("Index ({0}) must be less than the len! (len: ({1})", index, self.len);
+        }
+
+        unsafe { &mut *(self.inner.as_ptr().add(self.layout.size() * index).cast::<T>()) }
+    }
+
+    /// A `'static` reference to the value at `index`, the same way `iter_as` hands them out.
+    pub fn fetch_as<T: 'static>(&self, index: usize) -> &'static mut T {
+        if index >= self.len {
+            panic!("Index ({0}) must be less than the len! (len: ({1})", index, self.len);
+        }
+
+        unsafe { &mut *(self.inner.as_ptr().add(self.layout.size() * index).cast::<T>()) }
+    }
+
     pub fn index_cast<T>(&mut self, index: usize) -> &'static mut T 
     where
         T: Component
@@ -98,31 +224,26 @@ impl AnonVec {
 
             let size = self.layout.size();
 
-            // if this is the last element, drop and decrement.
-            if index == self.len - 1 {
-                // Drop the value inside if it needs it using the
-                // function we created for it earlier in Anon.
-                if let Some(drop) = self.drop {
-                    drop(self.inner.as_ptr().add(size * self.len));
-                }
-                // decrement to overwrite the value
-                self.len -= 1;
-                return;
-            }
-
-            // location to copy from (last element)
-            let src = self.inner.as_ptr().add(size * self.len);
             // location at index
             let dst = self.inner.as_ptr().add(size * index);
 
             // Drop the value inside if it needs it using the
-            // function we created for it earlier. 
+            // function we created for it earlier in Anon.
             if let Some(drop) = self.drop {
-                drop(self.inner.as_ptr().add(size * self.len));
+                drop(dst);
+            }
+
+            // if this is the last element, decrement and return.
+            if index == self.len - 1 {
+                self.len -= 1;
+                return;
             }
 
+            // location to copy from (last element)
+            let src = self.inner.as_ptr().add(size * (self.len - 1));
+
             // perform the copy to overwrite the memory
-            ptr::copy(src, dst, size);
+            ptr::copy_nonoverlapping(src, dst, size);
 
             self.len -= 1;
         }
@@ -145,12 +266,12 @@ impl AnonVec {
             }
 
             // location to copy from (last element)
-            let src = self.inner.as_ptr().add(size * self.len);
+            let src = self.inner.as_ptr().add(size * (self.len - 1));
             // location at index
             let dst = self.inner.as_ptr().add(size * index);
 
             // perform the copy to overwrite the memory
-            ptr::copy(src, dst, size);
+            ptr::copy_nonoverlapping(src, dst, size);
 
             self.len -= 1;
         }
@@ -168,31 +289,63 @@ impl AnonVec {
     }
 
     unsafe fn grow_if_full(&mut self) {
-        // calculate how much space is available
-        let available_space = self.capacity - self.len;
-
-        // If there is no available space, double it.
-        // Also, don't allocate anything if this is a ZST.
-        if available_space == 0 && self.layout.size() != 0 {
-            // Double the current capacity
-            let new_capacity = self.capacity * 2;
-            // Create a layout by duplicating an item for n times
-            let new_layout = Layout::from_size_align(
-                self.layout.size() * new_capacity,
-                self.layout.align(),
-            ).unwrap();
-
-            // Reassign self.data. 
-            let new_data;
-                if self.capacity == 0 {
-                    // if uninit, init
-                    new_data = alloc(new_layout);
-                } else {
-                    new_data = realloc(self.inner.as_ptr(), new_layout, new_capacity * self.layout.size());
-                };
-            
-            self.inner = NonNull::new(new_data).unwrap();
-            self.capacity = new_capacity;
+        // Zero-sized types never need an allocation.
+        if self.layout.size() == 0 || self.len < self.capacity {
+            return;
+        }
+
+        // Double the current capacity, starting from a small
+        // allocation if this vector was created empty.
+        let new_capacity = if self.capacity == 0 { 4 } else { self.capacity * 2 };
+        // Create a layout by duplicating an item for n times
+        let new_layout = Layout::from_size_align(
+            self.layout.size() * new_capacity,
+            self.layout.align(),
+        ).unwrap();
+
+        // Reassign self.data. 
+        let new_data;
+            if self.capacity == 0 {
+                // if uninit, init
+                new_data = alloc(new_layout);
+            } else {
+                let old_layout = Layout::from_size_align(
+                    self.layout.size() * self.capacity,
+                    self.layout.align(),
+                ).unwrap();
+                new_data = realloc(self.inner.as_ptr(), old_layout, new_layout.size());
+            };
+        
+        self.inner = NonNull::new(new_data).unwrap();
+        self.capacity = new_capacity;
+    }
+
+    /// Append a clone of the value at `index` in `src`.
+    pub fn push_clone(&mut self, src: &AnonVec, index: usize, clone: fn(*const u8, *mut u8)) {
+        if src.cmpid != self.cmpid {
+            panic!("Attempted to push a {0} into a vector of {1}!", src.name, self.name)
+        }
+
+        if index >= src.len {
+            panic!("Index ({0}) must be less than the len! (len: {1})", index, src.len);
+        }
+
+        unsafe {
+            self.grow_if_full();
+
+            let size = self.layout.size();
+            clone(src.inner.as_ptr().add(size * index), self.inner.as_ptr().add(size * self.len));
+        }
+
+        self.len += 1;
+    }
+
+    /// Replace the contents of this vector with clones of the values in `src`,
+    /// keeping the memory already allocated.
+    pub fn clone_from_with(&mut self, src: &AnonVec, clone: fn(*const u8, *mut u8)) {
+        self.clear();
+        for i in 0..src.len {
+            self.push_clone(src, i, clone);
         }
     }
 
@@ -215,6 +368,7 @@ pub struct Anon {
     drop: Option<fn(*mut u8)>,
     cmpid: ComponentId,
     layout: Layout,
+    name: &'static str,
 }
 
 impl Anon {
@@ -222,25 +376,18 @@ impl Anon {
     where
         T: Component,
     {
-        fn drop_as<T>(ptr: *mut u8) {
-            unsafe {
-                ptr.cast::<T>().drop_in_place();
-            }
-        }
-
         unsafe {
             let layout = Layout::new::<T>();
-            let ptr = NonNull::new(alloc(layout)).unwrap();
-            let drop: Option<fn(*mut u8)> = 
-                if needs_drop::<T>() { Some(drop_as::<T>) } else { None };
+            let ptr = if layout.size() == 0 { dangling(layout) } else { NonNull::new(alloc(layout)).unwrap() };
 
             ptr::write(ptr.as_ptr().cast::<T>(), val);
 
             Self {
                 inner: ptr,
-                drop,
+                drop: drop_fn::<T>(),
                 cmpid: T::__internal_id(),
                 layout,
+                name: type_name::<T>(),
             }
         }
     }
@@ -251,6 +398,7 @@ impl Anon {
             drop: None,
             cmpid: 0,
             layout: Layout::new::<i32>(),
+            name: "uninit",
         }
     }
 
@@ -258,6 +406,10 @@ impl Anon {
         self.cmpid
     }
 
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
     pub fn downcast<T>(&self) -> &T 
     where
         T: Component,
@@ -276,6 +428,22 @@ impl Anon {
         self.inner.as_ptr()
     }
 
+    /// A new `Anon` holding a clone of this value.
+    pub fn clone_with(&self, clone: fn(*const u8, *mut u8)) -> Anon {
+        unsafe {
+            let ptr = if self.layout.size() == 0 { dangling(self.layout) } else { NonNull::new(alloc(self.layout)).unwrap() };
+            clone(self.as_ptr(), ptr.as_ptr());
+
+            Anon {
+                inner: ptr,
+                drop: self.drop,
+                cmpid: self.cmpid,
+                layout: self.layout,
+                name: self.name,
+            }
+        }
+    }
+
     pub fn clear(&self) {
         if let Some(drop) = self.drop {
             drop(self.as_ptr())
@@ -349,5 +517,32 @@ impl<T> Iterator for AnonIterChain<T> {
     }
 }
 
+/// The function used to drop a `T` behind a type-erased pointer, if it needs one.
+pub fn drop_fn<T>() -> Option<fn(*mut u8)> {
+    fn drop_as<T>(ptr: *mut u8) {
+        unsafe {
+            ptr.cast::<T>().drop_in_place();
+        }
+    }
+
+    if needs_drop::<T>() { Some(drop_as::<T>) } else { None }
+}
+
+/// The function used to clone the `T` behind a type-erased pointer into uninitialized memory.
+pub fn clone_fn<T: Clone>() -> fn(*const u8, *mut u8) {
+    fn clone_as<T: Clone>(src: *const u8, dst: *mut u8) {
+        unsafe {
+            ptr::write(dst.cast::<T>(), (*src.cast::<T>()).clone());
+        }
+    }
+
+    clone_as::<T>
+}
+
+/// A well-aligned pointer for values that have not been allocated yet.
+fn dangling(layout: Layout) -> NonNull<u8> {
+    NonNull::new(layout.align() as *mut u8).unwrap()
+}
+
 unsafe impl Send for Anon {}
 unsafe impl Sync for Anon {}
\ No newline at end of file
@@ -7,10 +7,14 @@ use rayon::prelude::*;
 use indexmap::{IndexMap, IndexSet};
 use strata_traits::Component;
 
-use crate::anon::AnonIterChain;
-use crate::table::Table;
-use crate::entity::{Entity, EntityIndexChain};
-use crate::commands::Queue;
+use crate::anon::{Anon, AnonIterChain, AnonVec};
+use crate::table::{Table, TableState, Modify, DestroyType};
+use crate::entity::{ClaimError, Entity, EntityId, EntityIndex, EntityIndexChain, Entities};
+use crate::commands::{merge_modify, Deferred, Queue};
+use crate::components::Components;
+use crate::hooks::{HookEvent, HookKind};
+use crate::scheduler::SystemIndex;
+use crate::changes::{ChangeTick, Removals, Tick};
 
 pub type Column = usize;
 pub type TableIndex = usize;
@@ -20,8 +24,17 @@ pub struct Archetypes {
     archetypes: BTreeMap<Archetype, TableIndex>,
     tables: Vec<Table>,
     spawn: Mutex<BTreeMap<Archetype, Vec<Entity>>>,
-    cache: HashMap<u64, (Vec<ComponentId>, IndexSet<TableIndex>)>,
-    modify: Vec<Entity>,
+    cache: HashMap<Archetype, IndexSet<TableIndex>>,
+    modify: Vec<Modify>,
+    entities: Mutex<Entities>,
+    deferred: Mutex<Vec<Deferred>>,
+    buffered: Mutex<Vec<(SystemIndex, Queue)>>,
+    /// modifies of entities that are reserved but not spawned yet
+    pending: Mutex<IndexMap<EntityId, (Vec<Anon>, Vec<ComponentId>)>>,
+    /// components inserted into entities that were dead when the insert was queued
+    dropped: Mutex<Vec<(EntityId, ComponentId)>>,
+    tick: ChangeTick,
+    removals: Removals,
 }
 
 impl Archetypes {
@@ -32,77 +45,422 @@ impl Archetypes {
             spawn: Mutex::new(BTreeMap::new()),
             modify: Vec::new(),
             cache: HashMap::new(),
+            entities: Mutex::new(Entities::new()),
+            deferred: Mutex::new(Vec::new()),
+            buffered: Mutex::new(Vec::new()),
+            pending: Mutex::new(IndexMap::new()),
+            dropped: Mutex::new(Vec::new()),
+            tick: ChangeTick::default(),
+            removals: Removals::default(),
         }
     }
 
-    pub fn flush_queues(&mut self) {
-        let mut spawn = self.spawn.lock().unwrap();
+    pub fn tick(&self) -> &ChangeTick {
+        &self.tick
+    }
+
+    pub fn removals(&self) -> &Removals {
+        &self.removals
+    }
+
+    /// Forget removals from before the previous frame and start the next one.
+    pub fn end_frame(&mut self) {
+        let now = self.tick.advance();
+        self.removals.end_frame(now);
+    }
+
+    /// Reserve an id for an entity that will be spawned at the next flush.
+    pub fn reserve(&self) -> EntityId {
+        self.entities.lock().unwrap().alloc()
+    }
+
+    /// Reserve exactly `id`. Returns false if it is already alive.
+    pub fn claim(&self, id: EntityId) -> Result<bool, ClaimError> {
+        self.entities.lock().unwrap().alloc_at(id)
+    }
+
+    /// Free an id that was reserved but never spawned.
+    pub fn release(&self, id: EntityId) {
+        self.entities.lock().unwrap().free(id);
+    }
+
+    /// Where `id` is currently stored, if it is alive and has been spawned.
+    pub fn locate(&self, id: EntityId) -> Option<EntityIndex> {
+        self.entities.lock().unwrap().get(id)
+    }
+
+    pub fn contains(&self, id: EntityId) -> bool {
+        self.entities.lock().unwrap().contains(id)
+    }
+
+    pub fn get<C: Component>(&self, id: EntityId) -> Option<&C> {
+        let index = self.locate(id)?;
+        self.tables[index.table].get::<C>(index.col)
+    }
+
+    /// A component of the entity at `index` and its change tick, 
+    /// for queries that hand out `'static` references.
+    pub fn fetch<C: Component>(&self, index: EntityIndex) -> Option<(&'static mut C, &'static mut Tick)> {
+        self.tables[index.table].fetch::<C>(index.col)
+    }
+
+    /// A component, marked as changed.
+    pub fn get_mut<C: Component>(&mut self, id: EntityId) -> Option<&mut C> {
+        let index = self.entities.get_mut().unwrap().get(id)?;
+        self.tables[index.table].get_mut::<C>(index.col, self.tick.get())
+    }
+
+    /// The tick `C` was last inserted into or changed on `id` at.
+    pub fn last_changed<C: Component>(&self, id: EntityId) -> Option<Tick> {
+        let index = self.locate(id)?;
+        self.tables[index.table].tick(C::__internal_id(), index.col)
+    }
+
+    /// The last `C` queued to be inserted into `id` that the next flush will apply, 
+    /// so deferred work can see what earlier work in the same flush queued.
+    pub fn queued_mut<C: Component>(&mut self, id: EntityId) -> Option<&mut C> {
+        match self.entities.get_mut().unwrap().get(id) {
+            Some(index) => self.tables[index.table].queued_mut::<C>(index.col),
+            None => {
+                let (insert, _) = self.pending.get_mut().unwrap().get_mut(&id)?;
+                insert.iter().rev().find(|anon| anon.id() == C::__internal_id()).map(|anon| anon.downcast_mut::<C>())
+            }
+        }
+    }
+
+    /// Take the deferred work queued by commands, in the order it was queued.
+    pub fn take_deferred(&mut self) -> Vec<Deferred> {
+        std::mem::take(self.deferred.get_mut().unwrap())
+    }
+
+    /// Take the components that were inserted into dead entities and dropped.
+    pub fn take_dropped(&mut self) -> Vec<(EntityId, ComponentId)> {
+        std::mem::take(self.dropped.get_mut().unwrap())
+    }
+
+    /// True if anything is waiting to be flushed.
+    pub fn is_dirty(&self) -> bool {
+        !self.spawn.lock().unwrap().is_empty() 
+            || !self.deferred.lock().unwrap().is_empty()
+            || self.tables.iter().any(|table| table.needs_update() || table.needs_modify())
+    }
+
+    /// Record an `OnRemove` event for every hooked component about to be 
+    /// removed by the next flush, so the hooks can run while the data exists.
+    pub fn pending_removals(&self, components: &Components, events: &mut Vec<HookEvent>) {
+        for table in self.tables.iter() {
+            if table.needs_update() || table.needs_modify() {
+                table.pending_removals(components, events);
+            }
+        }
+    }
+
+    /// The ids of every entity the next flush will destroy.
+    pub fn pending_destroys(&self, out: &mut Vec<EntityId>) {
+        for table in self.tables.iter() {
+            if table.needs_update() {
+                table.pending_destroys(out);
+            }
+        }
+    }
+
+    /// Apply every queued spawn, destroy and modify. An `OnAdd`/`OnInsert` 
+    /// event is recorded in `events` for each hooked component that was added.
+    pub fn flush_queues(&mut self, components: &mut Components, events: &mut Vec<HookEvent>) {
+        let first_new = self.tables.len();
+        let tick = self.tick.get();
+        let mut spawn = std::mem::take(self.spawn.get_mut().unwrap());
         
         // Flush everything in "Spawn"
         while let Some((archetype, mut entities)) = spawn.pop_last() {
-            // get the first entity
-            if let Some(entity) = entities.pop() {
-                // check if the archetype already exists
-                if let Some(index) = self.archetypes.get(&entity.archetype) {
-                    self.tables[*index].spawn_group(entities);
-                } else {
-                // create the archetype
-                    let index = self.tables.len();
-
-                    if let Some(_) = self.archetypes.insert(archetype, index) {
-                        panic!("Attempted to create archetype that already exists!")
+            // check if the archetype already exists
+            let index = match self.archetypes.get(&archetype) {
+                Some(index) => {
+                    debug_assert!(
+                        *self.tables[*index].archetype() == archetype, 
+                        "Spawned entities into a table with a different archetype"
+                    );
+                    *index
+                }
+                // create the archetype from the first entity
+                None => match entities.pop() {
+                    Some(entity) => {
+                        let id = entity.id;
+                        let index = self.insert_table(archetype.clone(), Table::new(entity, tick));
+                        self.entities.get_mut().unwrap().set(id, EntityIndex { table: index, col: 0 });
+                        self.tables[index].archetype().ids().iter()
+                            .for_each(|cmp| added(components, events, *cmp, id));
+                        index
                     }
-                    self.tables.push(Table::new(entity));
-    
-                    // update the cache with the new archetype index
-                    self.cache.par_iter_mut().for_each(|(arch, (ids, indices))| {
-                        if self.tables[index].contains(ids) {
-                            indices.insert(index);
-                        }
-                    });
+                    None => panic!("Queued an empty vector for spawn!"),
                 }
-            } else {
-                panic!("Queued an empty vector for spawn!")
+            };
+
+            if !entities.is_empty() {
+                self.tables[index].spawn_group(entities);
             }
         }
 
         // get all the modifies
-        for table in self.tables.iter_mut() {
+        let mut modify = std::mem::take(&mut self.modify);
+        for (index, table) in self.tables.iter_mut().enumerate() {
             if table.needs_modify() {
-                table.process_modify(&mut self.modify);
+                table.drain_modify(index, &mut modify);
+            }
+        }
+
+        // move every modified entity along the archetype graph
+        while let Some(m) = modify.pop() {
+            self.apply_modify(m, components, events);
+        }
+        self.modify = modify;
+
+        // process the spawn and destroy queues inside the table.
+        let entities = self.entities.get_mut().unwrap();
+        let mut spawned = Vec::new();
+        for (index, table) in self.tables.iter_mut().enumerate() {
+            if table.needs_update() {
+                table.process_queues(index, entities, &mut spawned, tick, &mut self.removals);
+
+                for id in spawned.drain(..) {
+                    table.archetype().ids().iter()
+                        .for_each(|cmp| added(components, events, *cmp, id));
+                }
+            }
+        }
+
+        // modifies of entities that were just spawned are applied next pass
+        let pending = std::mem::take(self.pending.get_mut().unwrap());
+        for (id, (insert, remove)) in pending {
+            self.modify_entity(id, insert, remove);
+        }
+
+        // register the components of any tables created this flush.
+        for table in self.tables[first_new..].iter() {
+            table.register_columns(components);
+        }
+    }
+
+    /// The component `cmp` of `id`, pointing into its column.
+    pub fn anon(&self, id: EntityId, cmp: ComponentId) -> Option<Anon> {
+        let index = self.locate(id)?;
+        Some(self.tables[index.table].column(cmp)?.index(index.col))
+    }
+
+    /// The column holding `cmp` for `id`, and the entity's index in it.
+    pub fn column(&self, id: EntityId, cmp: ComponentId) -> Option<(&AnonVec, Column)> {
+        let index = self.locate(id)?;
+        Some((self.tables[index.table].column(cmp)?, index.col))
+    }
+
+    /// Like `column`, but stamps the component with the current tick.
+    pub fn column_mut(&mut self, id: EntityId, cmp: ComponentId) -> Option<(&mut AnonVec, Column)> {
+        let index = self.entities.get_mut().unwrap().get(id)?;
+        let tick = self.tick.get();
+        Some((self.tables[index.table].column_mut(cmp, index.col, tick)?, index.col))
+    }
+
+    /// The names of every stored component that was not registered as cloneable.
+    pub fn not_cloneable(&self, components: &Components) -> Vec<String> {
+        let mut out: Vec<String> = Vec::new();
+        for table in self.tables.iter().filter(|table| !table.is_empty()) {
+            for name in table.not_cloneable(components) {
+                if !out.contains(&name) {
+                    out.push(name);
+                }
             }
         }
+        out
+    }
+
+    /// A deep copy of every table and the entity allocator. Must be flushed, with every
+    /// stored component cloneable. Empty tables are copied too, so table indices match.
+    pub fn fork(&self, components: &Components) -> Archetypes {
+        Self {
+            archetypes: self.archetypes.clone(),
+            tables: self.tables.iter().map(|table| table.fork(components)).collect(),
+            spawn: Mutex::new(BTreeMap::new()),
+            cache: self.cache.clone(),
+            tick: ChangeTick::new(self.tick.get()),
+            removals: Removals::default(),
+            modify: Vec::new(),
+            entities: Mutex::new(self.entities.lock().unwrap().clone()),
+            deferred: Mutex::new(Vec::new()),
+            buffered: Mutex::new(Vec::new()),
+            pending: Mutex::new(IndexMap::new()),
+            dropped: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Copy every entity into `tables`, one state per table, reusing their memory.
+    pub fn save(&self, components: &Components, tables: &mut Vec<TableState>, entities: &mut Entities) {
+        tables.resize_with(self.tables.len(), TableState::default);
+        for (table, state) in self.tables.iter().zip(tables.iter_mut()) {
+            table.save(components, state);
+        }
+        entities.clone_from(&self.entities.lock().unwrap());
+    }
+
+    /// Restore the entities saved by `save`. Tables created since then are emptied.
+    /// Every restored component counts as changed, and every entity or component 
+    /// that is gone counts as removed.
+    pub fn load(&mut self, components: &Components, tables: &[TableState], entities: &Entities) {
+        let tick = self.tick.get();
+        let before: Vec<EntityId> = self.tables.iter().flat_map(|table| table.ids().iter().copied()).collect();
+        for (i, table) in self.tables.iter_mut().enumerate() {
+            match tables.get(i) {
+                Some(state) => table.load(components, state, tick, &mut self.removals),
+                None => table.clear(tick, &mut self.removals),
+            }
+        }
+        self.entities.get_mut().unwrap().clone_from(entities);
+
+        for id in before {
+            if !entities.contains(id) {
+                self.removals.despawn(id, tick);
+            }
+        }
+    }
+
+    pub fn tables(&self) -> &[Table] {
+        &self.tables
+    }
+
+    /// One line per table, listing its entity count and component names.
+    pub fn describe(&self, components: &Components) -> String {
+        let mut out = String::new();
+        for (index, table) in self.tables.iter().enumerate() {
+            let names: Vec<String> = table.archetype().ids().iter()
+                .map(|id| components.name(*id))
+                .collect();
+            out.push_str(&format!("table {} ({} entities): {}\n", index, table.len(), names.join(", ")));
+        }
+        out
+    }
 
-        // submit all the modifies
-        while let Some(entity) = self.modify.pop() {
-            if let Some(index) = self.archetypes.get(&entity.archetype) {
-                self.tables[*index].spawn(entity);
+    fn apply_modify(&mut self, modify: Modify, components: &Components, events: &mut Vec<HookEvent>) {
+        let Modify { table: src, col, insert, remove } = modify;
+
+        // later inserts of the same component win.
+        let mut unique: Vec<Anon> = Vec::with_capacity(insert.len());
+        for anon in insert {
+            if let Some(slot) = unique.iter_mut().find(|other| other.id() == anon.id()) {
+                slot.clear();
+                *slot = anon;
             } else {
-                let index = self.tables.len();
-                if let Some(_) = self.archetypes.insert(entity.archetype, index) {
-                    panic!("Attempted to create an archetype that already exists!")
+                unique.push(anon);
+            }
+        }
+
+        // walk the graph to find the table this entity ends up in.
+        let mut dst = src;
+        for id in remove.iter() {
+            if self.tables[dst].has(*id) && !unique.iter().any(|anon| anon.id() == *id) {
+                dst = self.remove_edge(dst, *id);
+            }
+        }
+        for anon in unique.iter() {
+            if !self.tables[dst].has(anon.id()) {
+                dst = self.add_edge(dst, anon);
+            }
+        }
+
+        // anything the entity didn't have before is being added.
+        let id = self.tables[src].ids()[col];
+        for anon in unique.iter() {
+            if self.tables[src].has(anon.id()) {
+                if components.has_hook(anon.id(), HookKind::OnInsert) {
+                    events.push(HookEvent::new(HookKind::OnInsert, anon.id(), id));
                 }
-                self.tables.push(Table::new(entity));
-                
-                // update the cache with the new archetype index
-                self.cache.par_iter_mut().for_each(|(arch, (ids, indices))| {
-                    if self.tables[index].contains(ids) {
-                        indices.insert(index);
-                    }
-                });
+            } else {
+                added(components, events, anon.id(), id);
             }
         }
 
-        // process the spawn and destroy queues inside the table.
-        for table in self.tables.iter_mut() {
-            if table.needs_update() {
-                table.process_queues()
+        let tick = self.tick.get();
+        if dst == src {
+            // the archetype didn't change, so overwrite in place.
+            let table = &mut self.tables[src];
+            for anon in unique {
+                table.replace(col, anon, tick);
+            }
+        } else {
+            let (from, to) = pair_mut(&mut self.tables, src, dst);
+            let (id, col) = from.move_to(col, to, unique, tick, &mut self.removals);
+            self.entities.get_mut().unwrap().set(id, EntityIndex { table: dst, col });
+        }
+    }
+
+    /// The table reached by adding `anon`'s component to `src`, creating it if needed.
+    fn add_edge(&mut self, src: TableIndex, anon: &Anon) -> TableIndex {
+        if let Some(dst) = self.tables[src].edges.add.get(&anon.id()) {
+            return *dst
+        }
+
+        let mut archetype = self.tables[src].archetype().clone();
+        archetype.add(anon.id());
+
+        let dst = match self.archetypes.get(&archetype) {
+            Some(index) => *index,
+            None => {
+                let mut columns = self.tables[src].empty_columns();
+                columns.push(AnonVec::empty_for(anon));
+                self.insert_table(archetype, Table::with_columns(columns))
             }
+        };
+
+        self.tables[src].edges.add.insert(anon.id(), dst);
+        self.tables[dst].edges.remove.insert(anon.id(), src);
+        dst
+    }
+
+    /// The table reached by removing `id` from `src`, creating it if needed.
+    fn remove_edge(&mut self, src: TableIndex, id: ComponentId) -> TableIndex {
+        if let Some(dst) = self.tables[src].edges.remove.get(&id) {
+            return *dst
         }
+
+        let mut columns = self.tables[src].empty_columns();
+        columns.retain(|column| column.id() != id);
+
+        let mut archetype = self.tables[src].archetype().clone();
+        archetype.remove(id);
+
+        let dst = match self.archetypes.get(&archetype) {
+            Some(index) => *index,
+            None => self.insert_table(archetype, Table::with_columns(columns)),
+        };
+
+        self.tables[src].edges.remove.insert(id, dst);
+        self.tables[dst].edges.add.insert(id, src);
+        dst
     }
 
-    pub fn query(&self, arch: Archetype) -> &IndexSet<TableIndex> {
-        if let Some((_, indices)) = self.cache.get(&arch.0) {
+    fn insert_table(&mut self, archetype: Archetype, table: Table) -> TableIndex {
+        debug_assert!(
+            *table.archetype() == archetype, 
+            "Attempted to create a table whose columns do not match its archetype"
+        );
+
+        let index = self.tables.len();
+        if let Some(_) = self.archetypes.insert(archetype, index) {
+            panic!("Attempted to create an archetype that already exists!")
+        }
+        self.tables.push(table);
+
+        // update the cache with the new archetype index
+        let tables = &self.tables;
+        self.cache.par_iter_mut().for_each(|(query, indices)| {
+            if tables[index].contains(query.ids()) {
+                indices.insert(index);
+            }
+        });
+
+        index
+    }
+
+    pub fn query(&self, arch: &Archetype) -> &IndexSet<TableIndex> {
+        if let Some(indices) = self.cache.get(arch) {
             indices
         } else {
             panic!("Attempted to get from a query that does not exist!")
@@ -115,7 +473,19 @@ impl Archetypes {
             arch.add(*id);
         }
 
-        self.cache.insert(arch.0, (ids, IndexSet::new()));
+        if self.cache.contains_key(&arch) {
+            return;
+        }
+
+        // include any tables that were created before this query
+        let mut indices = IndexSet::new();
+        for (index, table) in self.tables.iter().enumerate() {
+            if table.contains(arch.ids()) {
+                indices.insert(index);
+            }
+        }
+
+        self.cache.insert(arch, indices);
     }
 
     pub fn collect<C: Component>(&self, indices: &IndexSet<TableIndex>) -> AnonIterChain<C> {
@@ -128,6 +498,27 @@ impl Archetypes {
         chain
     }
 
+    /// The change ticks of `C`, in the same order as `collect::<C>`.
+    pub fn collect_ticks<C: Component>(&self, indices: &IndexSet<TableIndex>) -> AnonIterChain<Tick> {
+        let mut chain = AnonIterChain { iters: Vec::with_capacity(indices.len()) };
+        for index in indices.iter() {
+            if let Some(iter) = self.tables[*index].collect_ticks::<C>() {
+                chain.push(iter);
+            }
+        }
+        chain
+    }
+
+    pub fn collect_ids(&self, indices: &IndexSet<TableIndex>) -> AnonIterChain<EntityId> {
+        let mut chain = AnonIterChain { iters: Vec::with_capacity(indices.len()) };
+        for index in indices.iter() {
+            if let Some(iter) = self.tables[*index].collect_ids() {
+                chain.push(iter);
+            }
+        }
+        chain
+    }
+
     pub fn collect_indices(&self, indices: &IndexSet<TableIndex>) -> EntityIndexChain {
         let mut chain = EntityIndexChain { iters: Vec::with_capacity(indices.len()) };
         for index in indices.iter() {
@@ -138,9 +529,30 @@ impl Archetypes {
         chain
     }
 
+    /// Hold the commands of a running system until `queue_buffered`.
+    pub fn buffer(&self, system: SystemIndex, queue: Queue) {
+        self.buffered.lock().unwrap().push((system, queue));
+    }
+
+    /// Queue the commands held for each system, in system order. The sort is 
+    /// stable so a system's own command buffers keep the order they were dropped in.
+    pub fn queue_buffered(&self) {
+        let mut buffered = std::mem::take(&mut *self.buffered.lock().unwrap());
+        buffered.sort_by_key(|(system, _)| *system);
+        for (_, queue) in buffered {
+            self.queue(queue);
+        }
+    }
+
     pub fn queue(&self, mut queue: Queue) {
-        // queue spawns
-        if let Some(ref mut spawn) = queue.spawn {
+        // queue spawns, grouped by the components they ended up with
+        if let Some(spawning) = queue.spawn.take() {
+            let mut spawn: BTreeMap<Archetype, Vec<Entity>> = BTreeMap::new();
+            for mut entity in spawning.into_values() {
+                entity.hash();
+                spawn.entry(entity.archetype.clone()).or_default().push(entity);
+            }
+
             let mut selfspawn = self.spawn.lock().unwrap();
             while let Some((archetype, mut entities)) = spawn.pop_first() {
                 if let Some(index) = self.archetypes.get(&archetype) {
@@ -154,35 +566,106 @@ impl Archetypes {
                 }
             }
         }
-        // queue destroys
+        // queue deferred work
+        if let Some(ref mut deferred) = queue.deferred {
+            self.deferred.lock().unwrap().append(deferred);
+        }
+        // queue destroys, resolving each id to where it is stored right now
         if let Some(ref mut destroy) = queue.destroy {
-            while let Some((table, destroy)) = destroy.pop_first() {
+            let mut groups: BTreeMap<TableIndex, Vec<DestroyType>> = BTreeMap::new();
+            let entities = self.entities.lock().unwrap();
+            for id in destroy.drain(..) {
+                if let Some(index) = entities.get(id) {
+                    groups.entry(index.table).or_default().push(DestroyType::Drop(index.col));
+                }
+            }
+            drop(entities);
+
+            while let Some((table, destroy)) = groups.pop_first() {
                 self.tables[table].destroy_group(destroy);
             }
         }
         // queue modifies
-        if let Some(ref mut modify) = queue.modify {
-            while let Some((index, (insert, remove))) = modify.pop() {
-                self.tables[index.table].modify_group(index.col, insert, remove);
+        if let Some(modify) = queue.modify.take() {
+            for (id, (insert, remove)) in modify {
+                self.modify_entity(id, insert, remove);
+            }
+        }
+    }
+
+    /// Queue a modify on the table `id` is stored in. Entities that are reserved but 
+    /// not spawned yet are modified once they are, and inserts into dead ones are dropped.
+    fn modify_entity(&self, id: EntityId, insert: Vec<Anon>, remove: Vec<ComponentId>) {
+        let entities = self.entities.lock().unwrap();
+        match entities.get(id) {
+            Some(index) => self.tables[index.table].modify_group(index.col, insert, remove),
+            None if entities.contains(id) => {
+                let mut pending = self.pending.lock().unwrap();
+                merge_modify(pending.entry(id).or_default(), insert, remove);
+            }
+            None => {
+                let mut dropped = self.dropped.lock().unwrap();
+                for anon in insert {
+                    dropped.push((id, anon.id()));
+                    anon.clear();
+                }
             }
         }
     }
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
-pub struct Archetype(pub u64);
+/// Record the `OnAdd` and `OnInsert` events for a component added to `entity`.
+fn added(components: &Components, events: &mut Vec<HookEvent>, id: ComponentId, entity: EntityId) {
+    if components.has_hook(id, HookKind::OnAdd) {
+        events.push(HookEvent::new(HookKind::OnAdd, id, entity));
+    }
+    if components.has_hook(id, HookKind::OnInsert) {
+        events.push(HookEvent::new(HookKind::OnInsert, id, entity));
+    }
+}
+
+/// Borrow two different tables mutably at once.
+fn pair_mut(tables: &mut Vec<Table>, a: TableIndex, b: TableIndex) -> (&mut Table, &mut Table) {
+    if a < b {
+        let (left, right) = tables.split_at_mut(b);
+        (&mut left[a], &mut right[0])
+    } else {
+        let (left, right) = tables.split_at_mut(a);
+        (&mut right[0], &mut left[b])
+    }
+}
+
+/// The set of components stored together in a table, kept sorted
+/// so that two archetypes are equal only if their components are.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
+pub struct Archetype(Vec<ComponentId>);
 
 impl Archetype {
     pub const fn new() -> Self {
-        Self(0)
+        Self(Vec::new())
+    }
+
+    pub fn add(&mut self, id: ComponentId) {
+        if let Err(i) = self.0.binary_search(&id) {
+            self.0.insert(i, id);
+        }
     }
 
-    pub fn add(&mut self, mut id: u64) {
-        id = id.wrapping_mul(123456789123456789);
-        self.0 = self.0.wrapping_add(id.wrapping_shl((id % 32) as u32 + 1));
+    pub fn remove(&mut self, id: ComponentId) {
+        if let Ok(i) = self.0.binary_search(&id) {
+            self.0.remove(i);
+        }
+    }
+
+    pub fn contains(&self, id: ComponentId) -> bool {
+        self.0.binary_search(&id).is_ok()
+    }
+
+    pub fn ids(&self) -> &[ComponentId] {
+        &self.0
     }
 
     pub fn clear(&mut self) {
-        self.0 = 0
+        self.0.clear()
     }
-}
\ No newline at end of file
+}
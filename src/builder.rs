@@ -1,8 +1,23 @@
+use std::hash::Hash;
 
-use strata_traits::Resource;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use strata_traits::{Component, Resource};
 
 use crate::engine::Engine;
 use crate::systems::{IntoSystem, Stage};
+use crate::hooks::{ComponentHook, ComponentHooks};
+use crate::observers::{Event, Trigger};
+use crate::relations::{self, Rel, Relation};
+use crate::registry::MapEntities;
+use crate::reflect::Reflect;
+use crate::checksum::StableHash;
+use crate::index;
+
+/// A group of resources, systems and hooks that are loaded together.
+pub trait Plugin {
+    fn build(&self, builder: &mut EngineBuilder);
+}
 
 pub struct EngineBuilder {
     engine: Engine,
@@ -20,6 +35,174 @@ impl EngineBuilder {
         self
     }
 
+    /// Make command application deterministic, for lockstep and replays. Systems that use 
+    /// `Commands` no longer run in parallel, so the ids they reserve never change between runs.
+    /// Without it, commands are still applied in system order, but ids are reserved as systems
+    /// spawn, in whatever order parallel systems happen to run, so spawned ids can differ.
+    pub fn strict(&mut self, strict: bool) -> &mut Self {
+        self.engine.systems.set_strict(strict);
+        self
+    }
+
+    pub fn load_plugin<P: Plugin>(&mut self, plugin: P) -> &mut Self {
+        plugin.build(self);
+        self
+    }
+
+    /// Register a component up front, so it has a name and index
+    /// before it is first stored in a table.
+    pub fn register_component<C: Component>(&mut self) -> &mut Self {
+        self.engine.components.register::<C>();
+        self
+    }
+
+    /// Run `hook` whenever `C` is added to an entity that did not have it.
+    pub fn on_add<C: Component>(&mut self, hook: ComponentHook) -> &mut Self {
+        self.engine.components.hooks_mut::<C>().on_add = Some(hook);
+        self
+    }
+
+    /// Run `hook` whenever `C` is inserted, whether it is new or replaces a value.
+    pub fn on_insert<C: Component>(&mut self, hook: ComponentHook) -> &mut Self {
+        match self.engine.indexes.chained_mut(C::__internal_id()) {
+            Some(chained) => chained.on_insert = Some(hook),
+            None => self.engine.components.hooks_mut::<C>().on_insert = Some(hook),
+        }
+        self
+    }
+
+    /// Run `hook` before `C` is removed from an entity or the entity is destroyed.
+    pub fn on_remove<C: Component>(&mut self, hook: ComponentHook) -> &mut Self {
+        match self.engine.indexes.chained_mut(C::__internal_id()) {
+            Some(chained) => chained.on_remove = Some(hook),
+            None => self.engine.components.hooks_mut::<C>().on_remove = Some(hook),
+        }
+        self
+    }
+
+    /// Include `C` in snapshots. Components that are not registered are skipped when saving.
+    pub fn register_serializable<C>(&mut self) -> &mut Self
+    where
+        C: Component + Serialize + DeserializeOwned
+    {
+        self.engine.components.register::<C>();
+        self.engine.registry.register_component::<C>();
+        self
+    }
+
+    /// Include the resource `R` in snapshots.
+    pub fn register_serializable_resource<R>(&mut self) -> &mut Self
+    where
+        R: Resource + Serialize + DeserializeOwned + Send + Sync
+    {
+        self.engine.registry.register_resource::<R>();
+        self
+    }
+
+    /// Send `C` from a `ReplicationServer` to its clients, for entities that are `Replicated`.
+    /// Both the server and the clients must register it.
+    pub fn register_replicated<C>(&mut self) -> &mut Self
+    where
+        C: Component + Serialize + DeserializeOwned
+    {
+        self.register_serializable::<C>();
+        self.engine.registry.register_replicated(C::__internal_id());
+        self
+    }
+
+    /// Remap the entity ids stored in `C` when it is loaded into another engine.
+    pub fn register_map_entities<C: Component + MapEntities>(&mut self) -> &mut Self {
+        self.engine.registry.register_map_entities::<C>();
+        self
+    }
+
+    /// Allow `C` to be copied by `Engine::clone_entity`. Entities with 
+    /// components that are not registered can't be cloned.
+    pub fn register_clone<C: Component + Clone>(&mut self) -> &mut Self {
+        self.engine.components.register_clone::<C>();
+        self
+    }
+
+    /// Copy `R` into forks and saved states. Other resources are left out of
+    /// `Engine::fork` and are not rolled back by `Engine::load_state`.
+    pub fn register_rollback_resource<R: Resource + Clone + Send + Sync>(&mut self) -> &mut Self {
+        self.engine.registry.register_rollback::<R>();
+        self
+    }
+
+    /// Include `C` in `Engine::checksum`. Components that are not registered are ignored.
+    pub fn register_hash<C: Component + StableHash>(&mut self) -> &mut Self {
+        self.engine.components.register::<C>();
+        self.engine.registry.register_hash::<C>();
+        self
+    }
+
+    /// Make `C` readable and editable by name through `Engine::reflect`.
+    pub fn register_reflect<C: Component + Reflect>(&mut self) -> &mut Self {
+        self.engine.components.register::<C>();
+        self.engine.registry.register_reflect::<C>();
+        self
+    }
+
+    pub fn register_reflect_resource<R: Resource + Reflect>(&mut self) -> &mut Self {
+        self.engine.registry.register_reflect_resource::<R>();
+        self
+    }
+
+    /// Look up entities by their value of `C` with `Index<C>::get`. Values edited in
+    /// place through `Mut<C>` are re-indexed at `Stage::Late`. Any `on_insert` and
+    /// `on_remove` hooks of `C` still run, after the index is updated.
+    pub fn register_hash_index<C: Component + Hash + Eq + Clone>(&mut self) -> &mut Self {
+        if self.engine.indexes.register_hash::<C>() {
+            self.index_hooks::<C>();
+        }
+        self
+    }
+
+    /// Like `register_hash_index`, but ordered so `Index<C>::range` works too.
+    pub fn register_ordered_index<C: Component + Ord + Clone>(&mut self) -> &mut Self {
+        if self.engine.indexes.register_ordered::<C>() {
+            self.index_hooks::<C>();
+        }
+        self
+    }
+
+    fn index_hooks<C: Component + Clone + PartialEq>(&mut self) {
+        let hooks = self.engine.components.hooks_mut::<C>();
+        let chained = ComponentHooks {
+            on_add: None,
+            on_insert: hooks.on_insert.replace(index::on_insert_index::<C>),
+            on_remove: hooks.on_remove.replace(index::on_remove_index::<C>),
+        };
+        if let Some(hooks) = self.engine.indexes.chained_mut(C::__internal_id()) {
+            *hooks = chained;
+        }
+        self.load_system(index::update_index::<C>, Stage::Late);
+    }
+
+    /// Track `Rel<R>` so it can be looked up by target and cleaned up
+    /// when its target is destroyed. Unregistered relations are plain components.
+    pub fn register_relation<R: Relation>(&mut self) -> &mut Self {
+        self.engine.relations.register::<R>();
+        let hooks = self.engine.components.hooks_mut::<Rel<R>>();
+        hooks.on_insert = Some(relations::on_insert_rel::<R>);
+        hooks.on_remove = Some(relations::on_remove_rel::<R>);
+        self.engine.registry.register_component::<Rel<R>>();
+        self.engine.registry.register_map_entities::<Rel<R>>();
+        self.engine.components.register_clone::<Rel<R>>();
+        self
+    }
+
+    /// Spawn an observer of `E` when the engine is first flushed.
+    pub fn observe<E, F>(&mut self, observer: F) -> &mut Self
+    where
+        E: Event,
+        F: Fn(&mut Trigger<E>, &mut Engine) + Send + Sync + 'static,
+    {
+        self.engine.commands().observe(observer);
+        self
+    }
+
     pub fn load_system<F, P>(&mut self, system: F, stage: Stage) -> &mut Self
     where
         F: IntoSystem<P>, <F as IntoSystem<P>>::System: Sync + Send
@@ -36,6 +219,18 @@ impl EngineBuilder {
         self
     }
 
+    /// A sync point inside `stage`. Systems loaded after it see the commands of those 
+    /// loaded before it, rather than waiting for the end of the stage.
+    pub fn apply_deferred(&mut self, stage: Stage) -> &mut Self {
+        self.engine.systems.apply_deferred(stage);
+        self
+    }
+
+    pub fn apply_deferred_startup(&mut self, stage: Stage) -> &mut Self {
+        self.engine.systems.apply_deferred_startup(stage);
+        self
+    }
+
     pub fn build(mut self) -> Engine {
         self.engine.finalize();
         self.engine
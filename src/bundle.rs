@@ -0,0 +1,42 @@
+use strata_traits::Component;
+
+use crate::anon::Anon;
+use crate::archetypes::ComponentId;
+
+/// A group of components inserted or removed together, implemented for tuples of components.
+pub trait Bundle: Send + Sync + 'static {
+    fn into_anons(self, out: &mut Vec<Anon>);
+    fn ids(out: &mut Vec<ComponentId>);
+}
+
+macros::impl_bundle!(B1);
+macros::impl_bundle!(B1,B2);
+macros::impl_bundle!(B1,B2,B3);
+macros::impl_bundle!(B1,B2,B3,B4);
+macros::impl_bundle!(B1,B2,B3,B4,B5);
+macros::impl_bundle!(B1,B2,B3,B4,B5,B6);
+macros::impl_bundle!(B1,B2,B3,B4,B5,B6,B7);
+macros::impl_bundle!(B1,B2,B3,B4,B5,B6,B7,B8);
+
+pub mod macros {
+    macro_rules! impl_bundle {
+        ($($b:ident),*) => {
+            impl<$($b),*> Bundle for ($($b),*,)
+            where
+                $($b: Component + Send + Sync),*
+            {
+                #[allow(non_snake_case)]
+                fn into_anons(self, out: &mut Vec<Anon>) {
+                    let ($($b),*,) = self;
+                    $(out.push(Anon::new::<$b>($b));)*
+                }
+
+                fn ids(out: &mut Vec<ComponentId>) {
+                    $(out.push($b::__internal_id());)*
+                }
+            }
+        }
+    }
+
+    pub(crate) use impl_bundle;
+}
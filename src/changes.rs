@@ -0,0 +1,128 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use strata_traits::Component;
+
+use crate::archetypes::ComponentId;
+use crate::engine::Engine;
+use crate::entity::EntityId;
+use crate::scheduler::{Accessor, SystemIndex, UnsafeRef};
+use crate::systems::SystemParam;
+
+/// A point in the engine's history. Every insert and every mutable access
+/// stamps the component with the current tick.
+pub type Tick = u64;
+
+/// The engine's current tick. It starts at 1, so a reader that has
+/// never looked can pass 0 as `since` to see everything.
+pub struct ChangeTick(AtomicU64);
+
+impl ChangeTick {
+    pub fn new(tick: Tick) -> Self {
+        Self(AtomicU64::new(tick))
+    }
+
+    pub fn get(&self) -> Tick {
+        self.0.load(Ordering::Acquire)
+    }
+
+    /// Returns the current tick and moves on to the next one, so every change made
+    /// after this call has a tick greater than the one returned.
+    pub fn advance(&self) -> Tick {
+        self.0.fetch_add(1, Ordering::AcqRel)
+    }
+}
+
+impl Default for ChangeTick {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+/// The components removed from entities and the entities despawned over the last
+/// two frames, so a reader that runs once a frame never misses one.
+#[derive(Default)]
+pub struct Removals {
+    components: BTreeMap<ComponentId, Vec<(EntityId, Tick)>>,
+    despawned: Vec<(EntityId, Tick)>,
+    /// the tick the previous frame ended at
+    frame: Tick,
+}
+
+impl Removals {
+    pub fn component(&mut self, id: ComponentId, entity: EntityId, tick: Tick) {
+        self.components.entry(id).or_default().push((entity, tick));
+    }
+
+    pub fn despawn(&mut self, entity: EntityId, tick: Tick) {
+        self.despawned.push((entity, tick));
+    }
+
+    /// The entities `id` was removed from after `since`, in the order it was removed.
+    /// An entity may have had the component inserted again since.
+    pub fn removed(&self, id: ComponentId, since: Tick) -> impl Iterator<Item = EntityId> + '_ {
+        self.components.get(&id)
+            .into_iter()
+            .flat_map(move |removed| after(removed, since))
+    }
+
+    /// The entities despawned after `since`, in the order they were despawned.
+    pub fn despawned(&self, since: Tick) -> impl Iterator<Item = EntityId> + '_ {
+        after(&self.despawned, since)
+    }
+
+    /// Forget everything recorded before the previous frame ended.
+    pub fn end_frame(&mut self, now: Tick) {
+        let frame = self.frame;
+        for removed in self.components.values_mut() {
+            removed.retain(|(_, tick)| *tick > frame);
+        }
+        self.components.retain(|_, removed| !removed.is_empty());
+        self.despawned.retain(|(_, tick)| *tick > frame);
+        self.frame = now;
+    }
+}
+
+fn after(log: &[(EntityId, Tick)], since: Tick) -> impl Iterator<Item = EntityId> + '_ {
+    // the log is in tick order, so skip straight to the first entry after `since`.
+    let start = log.partition_point(|(_, tick)| *tick <= since);
+    log[start..].iter().map(|(entity, _)| *entity)
+}
+
+/// The engine's change tick and removals, for systems that keep track of what 
+/// changed since they last ran. Read a component's tick with `Ref::last_changed`.
+pub struct Ticks {
+    engine: UnsafeRef<Engine>,
+}
+
+impl Ticks {
+    /// Returns the current tick and moves on to the next one, see `Engine::advance_tick`.
+    pub fn advance(&self) -> Tick {
+        self.engine.get().archetypes.tick().advance()
+    }
+
+    /// Every entity `C` was removed from after `since`, see `Engine::removed`.
+    pub fn removed<C: Component>(&self, since: Tick) -> impl Iterator<Item = EntityId> + '_ {
+        self.engine.get().archetypes.removals().removed(C::__internal_id(), since)
+    }
+
+    /// Every entity despawned after `since`.
+    pub fn despawned(&self, since: Tick) -> impl Iterator<Item = EntityId> + '_ {
+        self.engine.get().archetypes.removals().despawned(since)
+    }
+}
+
+impl SystemParam for Ticks {
+    fn fetch_param(engine: UnsafeRef<Engine>, _system: SystemIndex) -> Self {
+        Self { engine }
+    }
+
+    fn fetch_access() -> Vec<Accessor> {
+        // removals only change during a flush, when no system is running.
+        Vec::new()
+    }
+
+    fn fetch_queries(_queries: &mut Vec<Vec<ComponentId>>) {
+        // do nothing
+    }
+}
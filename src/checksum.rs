@@ -0,0 +1,260 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::archetypes::ComponentId;
+use crate::engine::Engine;
+use crate::entity::EntityId;
+
+/// Hashes the same on every platform and build, unlike `std::hash::Hash`, so checksums
+/// can be compared between machines. Implement it with `impl_stable_hash!`.
+pub trait StableHash {
+    fn stable_hash(&self, hasher: &mut StableHasher);
+}
+
+/// FNV-1a over little endian bytes.
+#[derive(Copy, Clone)]
+pub struct StableHasher {
+    hash: u64,
+}
+
+impl StableHasher {
+    pub fn new() -> Self {
+        Self {
+            hash: 0xcbf29ce484222325,
+        }
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.hash ^= *byte as u64;
+            self.hash = self.hash.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    pub fn write_u64(&mut self, v: u64) {
+        self.write(&v.to_le_bytes());
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+macros::impl_stable_hash_value!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+impl StableHash for usize {
+    fn stable_hash(&self, hasher: &mut StableHasher) {
+        hasher.write_u64(*self as u64);
+    }
+}
+
+impl StableHash for isize {
+    fn stable_hash(&self, hasher: &mut StableHasher) {
+        hasher.write_u64(*self as i64 as u64);
+    }
+}
+
+/// Floats hash their bits, so `0.0` and `-0.0` differ.
+impl StableHash for f32 {
+    fn stable_hash(&self, hasher: &mut StableHasher) {
+        hasher.write(&self.to_bits().to_le_bytes());
+    }
+}
+
+impl StableHash for f64 {
+    fn stable_hash(&self, hasher: &mut StableHasher) {
+        hasher.write(&self.to_bits().to_le_bytes());
+    }
+}
+
+impl StableHash for bool {
+    fn stable_hash(&self, hasher: &mut StableHasher) {
+        hasher.write(&[*self as u8]);
+    }
+}
+
+impl StableHash for char {
+    fn stable_hash(&self, hasher: &mut StableHasher) {
+        hasher.write(&(*self as u32).to_le_bytes());
+    }
+}
+
+impl StableHash for String {
+    fn stable_hash(&self, hasher: &mut StableHasher) {
+        hasher.write_u64(self.len() as u64);
+        hasher.write(self.as_bytes());
+    }
+}
+
+impl StableHash for EntityId {
+    fn stable_hash(&self, hasher: &mut StableHasher) {
+        hasher.write_u64(self.to_bits());
+    }
+}
+
+impl<T: StableHash> StableHash for Option<T> {
+    fn stable_hash(&self, hasher: &mut StableHasher) {
+        match self {
+            Some(value) => {
+                hasher.write(&[1]);
+                value.stable_hash(hasher);
+            }
+            None => hasher.write(&[0]),
+        }
+    }
+}
+
+impl<T: StableHash> StableHash for Vec<T> {
+    fn stable_hash(&self, hasher: &mut StableHasher) {
+        hasher.write_u64(self.len() as u64);
+        self.iter().for_each(|value| value.stable_hash(hasher));
+    }
+}
+
+impl<T: StableHash, const N: usize> StableHash for [T; N] {
+    fn stable_hash(&self, hasher: &mut StableHasher) {
+        self.iter().for_each(|value| value.stable_hash(hasher));
+    }
+}
+
+/// A hash of every hashed component, from `Engine::checksum`. When two engines disagree,
+/// compare the breakdowns to find which archetype or component type differs.
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub struct WorldChecksum {
+    pub total: u64,
+    /// Sorted by the components in each archetype. Empty tables are left out.
+    pub archetypes: Vec<ArchetypeChecksum>,
+    /// Sorted by name.
+    pub components: Vec<ComponentChecksum>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct ArchetypeChecksum {
+    pub components: Vec<String>,
+    pub entities: usize,
+    pub hash: u64,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct ComponentChecksum {
+    pub name: String,
+    pub id: ComponentId,
+    pub hash: u64,
+}
+
+impl WorldChecksum {
+    /// The names of the components whose hashes differ, or that only one side has.
+    pub fn diff(&self, other: &WorldChecksum) -> Vec<String> {
+        let mut out = Vec::new();
+        for cmp in self.components.iter() {
+            if !other.components.iter().any(|o| o.id == cmp.id && o.hash == cmp.hash) {
+                out.push(cmp.name.clone());
+            }
+        }
+        for cmp in other.components.iter() {
+            if !self.components.iter().any(|o| o.id == cmp.id) {
+                out.push(cmp.name.clone());
+            }
+        }
+        out
+    }
+}
+
+/// Hash every entity in id order, so the result doesn't depend on where entities are stored.
+pub(crate) fn checksum(engine: &Engine) -> WorldChecksum {
+    let tables = engine.archetypes.tables();
+
+    let mut entities: Vec<(EntityId, usize, usize)> = Vec::new();
+    for (index, table) in tables.iter().enumerate() {
+        entities.extend(table.ids().iter().enumerate().map(|(col, id)| (*id, index, col)));
+    }
+    entities.sort_unstable_by_key(|(id, _, _)| (id.index(), id.generation()));
+
+    // the hashed components of each table, in id order
+    let hashed: Vec<Vec<_>> = tables.iter()
+        .map(|table| table.archetype().ids().iter()
+            .filter_map(|id| Some((*id, table.column(*id)?, engine.registry.hash(*id)?)))
+            .collect())
+        .collect();
+
+    let mut total = StableHasher::new();
+    let mut archetypes: Vec<Option<StableHasher>> = vec![None; tables.len()];
+    let mut components: BTreeMap<ComponentId, StableHasher> = BTreeMap::new();
+    for (entity, table, col) in entities {
+        total.write_u64(entity.to_bits());
+        let archetype = archetypes[table].get_or_insert_with(StableHasher::new);
+        archetype.write_u64(entity.to_bits());
+
+        for (id, column, hash) in hashed[table].iter() {
+            let mut value = StableHasher::new();
+            hash(column, col, &mut value);
+
+            total.write_u64(*id);
+            total.write_u64(value.finish());
+            archetype.write_u64(*id);
+            archetype.write_u64(value.finish());
+
+            let component = components.entry(*id).or_insert_with(StableHasher::new);
+            component.write_u64(entity.to_bits());
+            component.write_u64(value.finish());
+        }
+    }
+
+    let mut archetypes: Vec<ArchetypeChecksum> = archetypes.into_iter().enumerate()
+        .filter_map(|(index, hasher)| Some(ArchetypeChecksum {
+            components: tables[index].archetype().ids().iter().map(|id| engine.components.name(*id)).collect(),
+            entities: tables[index].len(),
+            hash: hasher?.finish(),
+        }))
+        .collect();
+    archetypes.sort_by(|a, b| a.components.cmp(&b.components));
+
+    let mut components: Vec<ComponentChecksum> = components.into_iter()
+        .map(|(id, hasher)| ComponentChecksum {
+            name: engine.components.name(id),
+            id,
+            hash: hasher.finish(),
+        })
+        .collect();
+    components.sort_by(|a, b| a.name.cmp(&b.name));
+
+    WorldChecksum {
+        total: total.finish(),
+        archetypes,
+        components,
+    }
+}
+
+pub mod macros {
+    /// Implement `StableHash` for a struct by hashing the named fields in order.
+    ///
+    /// ```ignore
+    /// impl_stable_hash!(Health { current, max });
+    /// ```
+    macro_rules! impl_stable_hash {
+        ($t:ident { $($field:ident),* $(,)? }) => {
+            impl $crate::checksum::StableHash for $t {
+                fn stable_hash(&self, hasher: &mut $crate::checksum::StableHasher) {
+                    $($crate::checksum::StableHash::stable_hash(&self.$field, hasher);)*
+                }
+            }
+        };
+    }
+
+    /// Implement `StableHash` for integers by hashing their little endian bytes.
+    macro_rules! impl_stable_hash_value {
+        ($($t:ty),*) => {
+            $(
+                impl StableHash for $t {
+                    fn stable_hash(&self, hasher: &mut StableHasher) {
+                        hasher.write(&self.to_le_bytes());
+                    }
+                }
+            )*
+        };
+    }
+
+    pub(crate) use impl_stable_hash;
+    pub(crate) use impl_stable_hash_value;
+}
@@ -0,0 +1,100 @@
+use std::fmt;
+
+use strata_traits::Component;
+
+use crate::archetypes::ComponentId;
+use crate::commands::EntityCommands;
+use crate::engine::Engine;
+use crate::entity::{Entity, EntityId};
+use crate::hierarchy::{self, Children, Parent};
+
+#[derive(Debug)]
+pub enum CloneError {
+    /// The entity is dead or has not been spawned yet.
+    NoSuchEntity(EntityId),
+    /// The names of the components that were not registered with `EngineBuilder::register_clone`.
+    NotCloneable(Vec<String>),
+}
+
+impl fmt::Display for CloneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CloneError::NoSuchEntity(id) => write!(f, "entity {:?} does not exist", id),
+            CloneError::NotCloneable(names) => write!(f, "components are not cloneable: {}", names.join(", ")),
+        }
+    }
+}
+
+impl std::error::Error for CloneError {}
+
+impl<'a> EntityCommands<'a> {
+    /// Queue a copy of this entity to be spawned, returning its id. The copy has the
+    /// same parent but no children. Fails if any component is not cloneable.
+    ///
+    /// The entity is copied as it is when the commands are applied. If it was despawned
+    /// by then nothing is spawned, and if it gained a component that is not cloneable 
+    /// the flush panics.
+    pub fn clone_entity(&mut self) -> Result<EntityId, CloneError> {
+        let source = self.id();
+        check(self.commands().engine(), source)?;
+
+        let target = self.commands().reserve();
+        self.commands().defer(move |engine| match clone_into(engine, source, target) {
+            Ok(()) | Err(CloneError::NoSuchEntity(_)) => (),
+            Err(e) => panic!("Attempted to clone entity {:?}, but {}!", source, e),
+        });
+        Ok(target)
+    }
+}
+
+/// Fail if `entity` doesn't exist or has components that can't be cloned.
+pub(crate) fn check(engine: &Engine, entity: EntityId) -> Result<(), CloneError> {
+    let index = engine.archetypes.locate(entity).ok_or(CloneError::NoSuchEntity(entity))?;
+    let table = &engine.archetypes.tables()[index.table];
+
+    let missing: Vec<String> = table.archetype().ids().iter()
+        .filter(|id| !is_hierarchy(**id))
+        .filter(|id| engine.components.get(**id).and_then(|info| info.clone_fn()).is_none())
+        .map(|id| engine.components.name(*id))
+        .collect();
+
+    if missing.is_empty() { Ok(()) } else { Err(CloneError::NotCloneable(missing)) }
+}
+
+/// Queue `target` to be spawned with a copy of every component of `source`, as a child
+/// of the same parent. `target` must be reserved and not spawned, and is released if
+/// `source` can't be copied.
+pub(crate) fn clone_into(engine: &mut Engine, source: EntityId, target: EntityId) -> Result<(), CloneError> {
+    if let Err(e) = check(engine, source) {
+        engine.archetypes.release(target);
+        return Err(e);
+    }
+
+    let index = engine.archetypes.locate(source).unwrap();
+    let mut entity = Entity::new(target);
+    for id in engine.archetypes.tables()[index.table].archetype().ids() {
+        if is_hierarchy(*id) {
+            continue;
+        }
+        let clone = engine.components.get(*id).and_then(|info| info.clone_fn()).unwrap();
+        let anon = engine.archetypes.anon(source, *id).unwrap();
+        entity.insert_anon(anon.clone_with(clone));
+    }
+
+    let parent = hierarchy::parent_of(engine, source);
+    if let Some(parent) = parent {
+        entity.insert(Parent(parent));
+    }
+
+    let mut commands = engine.commands();
+    commands.spawn_entity(entity);
+    if let Some(parent) = parent {
+        commands.defer(move |engine| hierarchy::add_child(engine, parent, target));
+    }
+    Ok(())
+}
+
+/// The hierarchy is rebuilt for the copy instead of being cloned.
+fn is_hierarchy(id: ComponentId) -> bool {
+    id == Parent::__internal_id() || id == Children::__internal_id()
+}
@@ -1,67 +1,266 @@
 
-use std::collections::BTreeMap;
 use std::sync::Arc;
 
 use indexmap::IndexMap;
 use strata_traits::Component;
 
 use crate::entity::Entity;
-use crate::archetypes::{Archetype, ComponentId};
+use crate::archetypes::ComponentId;
 use crate::anon::Anon;
 use crate::archetypes::TableIndex;
 use crate::table::DestroyType;
 use crate::engine::Engine;
 use crate::systems::SystemParam;
 use crate::scheduler::Accessor;
-use crate::entity::EntityIndex;
+use crate::entity::{ClaimError, EntityId, EntityIndex};
 use crate::resources::Resources;
 use crate::archetypes::Archetypes;
-use crate::scheduler::UnsafeRef;
+use crate::scheduler::{SystemIndex, UnsafeRef};
+use crate::observers::{self, Event, Observer, Trigger};
+use crate::bundle::Bundle;
+
+/// Work that needs exclusive access to the engine, run after the queues are flushed.
+pub type Deferred = Box<dyn FnOnce(&mut Engine) + Send + Sync>;
+
+/// Structural work queued with `Commands::add`. Commands are applied at the 
+/// next flush, after spawns, destroys and modifies, in the order they were queued.
+pub trait Command: Send + Sync + 'static {
+    fn apply(self, engine: &mut Engine);
+}
+
+impl<F> Command for F
+where
+    F: FnOnce(&mut Engine) + Send + Sync + 'static
+{
+    fn apply(self, engine: &mut Engine) {
+        self(engine)
+    }
+}
 
 pub struct Commands {
     engine: UnsafeRef<Engine>,
     queue: Queue,
+    /// the system these commands were fetched by, if any
+    system: Option<SystemIndex>,
 }
 
 impl Commands {
-    pub fn spawn<F>(&mut self, predicate: F)
+    pub(crate) fn new(engine: UnsafeRef<Engine>) -> Self {
+        Self {
+            engine,
+            queue: Queue::default(),
+            system: None,
+        }
+    }
+
+    /// Queue an entity to be spawned. The returned commands 
+    /// target its id, which is valid immediately. Inside systems,
+    /// ids only match between runs in strict mode, see `EngineBuilder::strict`.
+    pub fn spawn<F>(&mut self, predicate: F) -> EntityCommands<'_>
     where
         F: Fn(&mut Entity) 
     {
-        let mut entity = Entity::new();
+        let mut entity = Entity::new(self.engine.get().archetypes.reserve());
         predicate(&mut entity);
+        let id = entity.id();
+        self.queue.spawn(entity);
+        self.entity(id)
+    }
+
+    /// Queue an entity that was built with an id from `reserve`.
+    pub(crate) fn spawn_entity(&mut self, entity: Entity) {
         self.queue.spawn(entity);
     }
 
-    pub fn destroy(&mut self, index: EntityIndex) {
-        self.queue.destroy(index);
+    /// Reserve an id for an entity spawned later with `spawn_entity`.
+    pub(crate) fn reserve(&self) -> EntityId {
+        self.engine.get().archetypes.reserve()
     }
 
-    pub fn insert<C: Component>(&mut self, index: EntityIndex, cmp: C) {
-        self.queue.insert(Anon::new::<C>(cmp), index);
+    /// Take everything queued so far, leaving these commands empty.
+    pub(crate) fn take_queue(&mut self) -> Queue {
+        std::mem::take(&mut self.queue)
     }
 
-    pub fn remove<C: Component>(&mut self, index: EntityIndex) {
-        self.queue.remove(index, C::__internal_id());
+    pub(crate) fn engine(&self) -> &Engine {
+        self.engine.get()
+    }
+
+    /// Queue an entity with the components in `bundle` to be spawned.
+    pub fn spawn_bundle<B: Bundle>(&mut self, bundle: B) -> EntityCommands<'_> {
+        let mut entity = Entity::new(self.engine.get().archetypes.reserve());
+        entity.insert_bundle(bundle);
+        let id = entity.id();
+        self.queue.spawn(entity);
+        self.entity(id)
+    }
+
+    /// Commands for the entity with exactly this id, spawning an empty entity 
+    /// with it if it is not alive. Used for ids assigned elsewhere, e.g. by a server.
+    /// Fails if a living entity has its index, or the index is out of range.
+    pub fn get_or_spawn(&mut self, id: EntityId) -> Result<EntityCommands<'_>, ClaimError> {
+        if self.engine.get().archetypes.claim(id)? {
+            self.queue.spawn_claimed(Entity::new(id));
+        }
+        Ok(self.entity(id))
+    }
+
+    /// Entities that are dead or have not been spawned by 
+    /// the time the commands are queued are ignored.
+    pub fn destroy(&mut self, id: EntityId) {
+        // an entity spawned by these commands never reaches a table
+        if self.queue.cancel_spawn(id) {
+            self.engine.get().archetypes.release(id);
+        } else {
+            self.queue.destroy(id);
+        }
+    }
+
+    pub fn insert<C: Component>(&mut self, id: EntityId, cmp: C) {
+        self.queue.insert(Anon::new::<C>(cmp), id);
+    }
+
+    pub fn remove<C: Component>(&mut self, id: EntityId) {
+        self.queue.remove(id, C::__internal_id());
+    }
+
+    pub(crate) fn insert_anon(&mut self, id: EntityId, anon: Anon) {
+        self.queue.insert(anon, id);
+    }
+
+    pub(crate) fn remove_id(&mut self, id: EntityId, cmp: ComponentId) {
+        self.queue.remove(id, cmp);
+    }
+
+    pub fn insert_bundle<B: Bundle>(&mut self, id: EntityId, bundle: B) {
+        let mut anons = Vec::new();
+        bundle.into_anons(&mut anons);
+        for anon in anons {
+            self.queue.insert(anon, id);
+        }
+    }
+
+    pub fn remove_bundle<B: Bundle>(&mut self, id: EntityId) {
+        let mut ids = Vec::new();
+        B::ids(&mut ids);
+        for cmp in ids {
+            self.queue.remove(id, cmp);
+        }
+    }
+
+    /// Commands for a single entity.
+    pub fn entity(&mut self, id: EntityId) -> EntityCommands<'_> {
+        EntityCommands {
+            id,
+            commands: self,
+        }
+    }
+
+    /// Apply `command` with exclusive access to the engine at the next flush.
+    pub fn add<C: Command>(&mut self, command: C) {
+        self.queue.defer(Box::new(move |engine: &mut Engine| command.apply(engine)));
+    }
+
+    /// Run `f` with exclusive access to the engine at the next flush.
+    pub(crate) fn defer<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut Engine) + Send + Sync + 'static
+    {
+        self.queue.defer(Box::new(f));
+    }
+
+    /// Run every global observer of `E` at the next flush.
+    pub fn trigger<E: Event>(&mut self, event: E) {
+        self.queue.defer(Box::new(move |engine: &mut Engine| {
+            observers::trigger(engine, event, None)
+        }));
+    }
+
+    /// Run the observers of `E` watching `target` at the next flush, 
+    /// as well as every global observer of `E`.
+    pub fn trigger_targets<E: Event>(&mut self, target: EntityId, event: E) {
+        self.queue.defer(Box::new(move |engine: &mut Engine| {
+            observers::trigger(engine, event, Some(target))
+        }));
+    }
+
+    /// Spawn an entity observing `E`, returning its id so it can be despawned later.
+    pub fn observe<E, F>(&mut self, observer: F) -> EntityId
+    where
+        E: Event,
+        F: Fn(&mut Trigger<E>, &mut Engine) + Send + Sync + 'static,
+    {
+        let mut entity = Entity::new(self.engine.get().archetypes.reserve());
+        entity.insert(Observer::new(observer));
+        let id = entity.id();
+        self.queue.spawn(entity);
+        id
+    }
+}
+
+/// Commands targeting a single entity, from `Commands::entity` or `Commands::spawn`.
+pub struct EntityCommands<'a> {
+    id: EntityId,
+    commands: &'a mut Commands,
+}
+
+impl<'a> EntityCommands<'a> {
+    pub fn id(&self) -> EntityId {
+        self.id
+    }
+
+    pub fn commands(&mut self) -> &mut Commands {
+        self.commands
+    }
+
+    pub fn insert<C: Component>(&mut self, cmp: C) -> &mut Self {
+        self.commands.insert(self.id, cmp);
+        self
+    }
+
+    pub fn remove<C: Component>(&mut self) -> &mut Self {
+        self.commands.remove::<C>(self.id);
+        self
+    }
+
+    pub fn insert_bundle<B: Bundle>(&mut self, bundle: B) -> &mut Self {
+        self.commands.insert_bundle(self.id, bundle);
+        self
+    }
+
+    pub fn remove_bundle<B: Bundle>(&mut self) -> &mut Self {
+        self.commands.remove_bundle::<B>(self.id);
+        self
+    }
+
+    pub fn despawn(&mut self) {
+        self.commands.destroy(self.id);
     }
 }
 
 impl Drop for Commands {
     fn drop(&mut self) {
-        self.engine.get().archetypes.queue(std::mem::take(&mut self.queue));
+        let queue = std::mem::take(&mut self.queue);
+        match self.system {
+            // systems run in parallel, so their queues are held until the 
+            // stage ends and queued in system order.
+            Some(system) => self.engine.get().archetypes.buffer(system, queue),
+            None => self.engine.get().archetypes.queue(queue),
+        }
     }
 }
 
 impl SystemParam for Commands {
-    fn fetch_param(engine: UnsafeRef<Engine>) -> Self {
-        Commands {
+    fn fetch_param(engine: UnsafeRef<Engine>, system: SystemIndex) -> Self {
+        Self {
             engine,
             queue: Queue::default(),
+            system: Some(system),
         }
     }
 
     fn fetch_access() -> Vec<Accessor> {
-        vec![Accessor::None]
+        vec![Accessor::Commands]
     }
 
     fn fetch_queries(queries: &mut Vec<Vec<ComponentId>>) {
@@ -70,75 +269,140 @@ impl SystemParam for Commands {
 }
 
 pub struct Queue {
-    pub spawn: Option<BTreeMap<Archetype, Vec<Entity>>>,
-    pub destroy: Option<BTreeMap<TableIndex, Vec<DestroyType>>>,
-    pub modify: Option<IndexMap<EntityIndex, (Vec<Anon>, Vec<ComponentId>)>>,
+    pub spawn: Option<IndexMap<EntityId, Entity>>,
+    pub claimed: Option<Vec<EntityId>>,
+    pub destroy: Option<Vec<EntityId>>,
+    pub modify: Option<IndexMap<EntityId, (Vec<Anon>, Vec<ComponentId>)>>,
+    pub deferred: Option<Vec<Deferred>>,
 }
 
 impl Queue {
-    pub fn spawn(&mut self, mut entity: Entity) {
-        entity.hash();
-
-        if let Some(ref mut spawn) = self.spawn {
-            if let Some(entities) = spawn.get_mut(&entity.archetype) {
-                entities.push(entity);
-            } else {
-                spawn.insert(entity.archetype, vec![entity]);
+    /// Entities are grouped by archetype when the queue is queued, 
+    /// so components can still be added to them until then.
+    pub fn spawn(&mut self, entity: Entity) {
+        self.spawn.get_or_insert_with(IndexMap::new).insert(entity.id, entity);
+    }
+
+    /// Spawn an entity whose id was claimed rather than reserved.
+    pub fn spawn_claimed(&mut self, entity: Entity) {
+        self.claimed.get_or_insert_with(Vec::new).push(entity.id);
+        self.spawn(entity);
+    }
+
+    fn spawning(&mut self, id: EntityId) -> Option<&mut Entity> {
+        self.spawn.as_mut()?.get_mut(&id)
+    }
+
+    /// Drop an entity spawned by this queue. Returns false if it wasn't.
+    pub fn cancel_spawn(&mut self, id: EntityId) -> bool {
+        match self.spawn.as_mut().and_then(|spawn| spawn.swap_remove(&id)) {
+            Some(entity) => {
+                entity.components.iter().for_each(|anon| anon.clear());
+                true
             }
+            None => false,
+        }
+    }
+
+    pub fn defer(&mut self, deferred: Deferred) {
+        if let Some(ref mut list) = self.deferred {
+            list.push(deferred);
         } else {
-            let mut spawn = Some(BTreeMap::new());
-            spawn.as_mut().unwrap().insert(entity.archetype, vec![entity]);
-            self.spawn = spawn;
+            self.deferred = Some(vec![deferred]);
         }
     }
 
-    pub fn destroy(&mut self, index: EntityIndex) {
+    pub fn destroy(&mut self, id: EntityId) {
         if let Some(ref mut destroy) = self.destroy {
-            if let Some(destroys) = destroy.get_mut(&index.table) {
-                destroys.push(DestroyType::Drop(index.col));
-            }
+            destroy.push(id);
         } else {
-            let mut destroy = Some(BTreeMap::new());
-            destroy.as_mut().unwrap().insert(index.table, vec![DestroyType::Drop(index.col)]);
-            self.destroy = destroy;
+            self.destroy = Some(vec![id]);
         }
     }
 
-    pub fn insert(&mut self, anon: Anon, index: EntityIndex) {
-        if let Some(ref mut modify) = self.modify {
-            if let Some((insert, _)) = modify.get_mut(&index) {
-                insert.push(anon);
-            } else {
-                modify.insert(index, (vec![anon], Vec::new()));
-            }
-        } else {
-            let mut modify = Some(IndexMap::new());
-            modify.as_mut().unwrap().insert(index, (vec![anon], Vec::new()));
-            self.modify = modify;
+    pub fn insert(&mut self, anon: Anon, id: EntityId) {
+        if let Some(entity) = self.spawning(id) {
+            entity.insert_anon(anon);
+            return;
         }
+
+        let modify = self.modify.get_or_insert_with(IndexMap::new);
+        merge_modify(modify.entry(id).or_default(), vec![anon], Vec::new());
     }
 
-    pub fn remove(&mut self, index: EntityIndex, id: ComponentId) {
-        if let Some(ref mut modify) = self.modify {
-            if let Some((_, destroy)) = modify.get_mut(&index) {
-                destroy.push(id);
-            } else {
-                modify.insert(index, (Vec::new(), vec![id]));
-            }
-        } else {
-            let mut modify = Some(IndexMap::new());
-            modify.as_mut().unwrap().insert(index, (Vec::new(), vec![id]));
-            self.modify = modify;
+    pub fn remove(&mut self, id: EntityId, cmp: ComponentId) {
+        if let Some(entity) = self.spawning(id) {
+            entity.remove(cmp);
+            return;
         }
+
+        let modify = self.modify.get_or_insert_with(IndexMap::new);
+        merge_modify(modify.entry(id).or_default(), Vec::new(), vec![cmp]);
+    }
+}
+
+/// Append later inserts and removes to the ones queued for an entity. A removal
+/// drops the inserts of its component queued before it, and a removal followed by
+/// an insert of the same component is skipped when the modify is applied.
+pub(crate) fn merge_modify(queued: &mut (Vec<Anon>, Vec<ComponentId>), insert: Vec<Anon>, remove: Vec<ComponentId>) {
+    let (ins, rem) = queued;
+    for id in remove {
+        ins.retain(|anon| {
+            if anon.id() == id {
+                anon.clear();
+                return false;
+            }
+            true
+        });
+        rem.push(id);
     }
+    ins.extend(insert);
 }
 
 impl Default for Queue {
     fn default() -> Self {
         Queue {
             spawn: None,
+            claimed: None,
             destroy: None,
             modify: None,
+            deferred: None,
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+    use crate::builder::EngineBuilder;
+    use crate::components::impl_component;
+
+    struct Health(u32);
+    impl_component!(Health);
+
+    static REMOVED: AtomicU32 = AtomicU32::new(0);
+
+    fn count_removal(_: &mut Engine, _: EntityId, _: &mut Commands) {
+        REMOVED.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn chained_insert_and_remove_keep_their_order() {
+        let mut builder = EngineBuilder::new();
+        builder.on_remove::<Health>(count_removal);
+        let mut engine = builder.build();
+        let entity = engine.spawn(|e| e.insert(Health(1)));
+
+        engine.commands().entity(entity).insert(Health(2)).remove::<Health>();
+        engine.flush();
+        assert!(engine.get::<Health>(entity).is_none());
+        assert_eq!(REMOVED.load(Ordering::SeqCst), 1);
+
+        engine.insert(entity, Health(3));
+        engine.commands().entity(entity).remove::<Health>().insert(Health(4));
+        engine.flush();
+        assert_eq!(engine.get::<Health>(entity).map(|health| health.0), Some(4));
+        assert_eq!(REMOVED.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,229 @@
+use std::alloc::Layout;
+use std::any::type_name;
+use std::collections::HashMap;
+
+use strata_traits::Component;
+
+use crate::anon::{clone_fn, drop_fn, AnonVec};
+use crate::archetypes::ComponentId;
+use crate::hooks::{ComponentHook, ComponentHooks, HookKind};
+
+/// Runtime information about every component the engine has seen.
+#[derive(Clone)]
+pub struct Components {
+    infos: Vec<ComponentInfo>,
+    indices: HashMap<ComponentId, usize>,
+}
+
+impl Components {
+    pub fn new() -> Self {
+        Self {
+            infos: Vec::new(),
+            indices: HashMap::new(),
+        }
+    }
+
+    /// Register `C`, returning its dense index.
+    /// Registering a component more than once is a no-op.
+    pub fn register<C: Component>(&mut self) -> usize {
+        self.insert(
+            C::__internal_id(),
+            type_name::<C>(),
+            Layout::new::<C>(),
+            drop_fn::<C>()
+        )
+    }
+
+    /// Register the component stored in a column, returning its dense index.
+    pub fn register_column(&mut self, column: &AnonVec) -> usize {
+        self.insert(column.id(), column.name(), column.layout(), column.drop_fn())
+    }
+
+    fn insert(
+        &mut self,
+        id: ComponentId,
+        type_name: &'static str,
+        layout: Layout,
+        drop: Option<fn(*mut u8)>
+    ) -> usize {
+        if let Some(index) = self.indices.get(&id) {
+            return *index
+        }
+
+        let index = self.infos.len();
+        self.infos.push(ComponentInfo {
+            id,
+            index,
+            name: short_name(type_name),
+            type_name,
+            layout,
+            drop,
+            storage: StorageType::Table,
+            hooks: ComponentHooks::default(),
+            clone: None,
+        });
+        self.indices.insert(id, index);
+        index
+    }
+
+    /// Allow `C` to be copied by `Engine::clone_entity`.
+    pub fn register_clone<C: Component + Clone>(&mut self) {
+        let index = self.register::<C>();
+        self.infos[index].clone = Some(clone_fn::<C>());
+    }
+
+    /// The hooks for `C`, registering it if needed.
+    pub fn hooks_mut<C: Component>(&mut self) -> &mut ComponentHooks {
+        let index = self.register::<C>();
+        &mut self.infos[index].hooks
+    }
+
+    pub fn hook(&self, id: ComponentId, kind: HookKind) -> Option<ComponentHook> {
+        self.get(id).and_then(|info| info.hooks.get(kind))
+    }
+
+    pub fn has_hook(&self, id: ComponentId, kind: HookKind) -> bool {
+        self.hook(id, kind).is_some()
+    }
+
+    pub fn get(&self, id: ComponentId) -> Option<&ComponentInfo> {
+        self.indices.get(&id).map(|index| &self.infos[*index])
+    }
+
+    pub fn get_by_index(&self, index: usize) -> Option<&ComponentInfo> {
+        self.infos.get(index)
+    }
+
+    /// Find a component by its short or full type name.
+    pub fn get_by_name(&self, name: &str) -> Option<&ComponentInfo> {
+        self.infos.iter().find(|info| info.name == name || info.type_name == name)
+    }
+
+    pub fn index_of(&self, id: ComponentId) -> Option<usize> {
+        self.indices.get(&id).copied()
+    }
+
+    /// The short name of a component, or its id in hex if it was never registered.
+    pub fn name(&self, id: ComponentId) -> String {
+        match self.get(id) {
+            Some(info) => info.name.clone(),
+            None => format!("{:#x}", id),
+        }
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, ComponentInfo> {
+        self.infos.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.infos.len()
+    }
+}
+
+#[derive(Clone)]
+pub struct ComponentInfo {
+    id: ComponentId,
+    index: usize,
+    name: String,
+    type_name: &'static str,
+    layout: Layout,
+    drop: Option<fn(*mut u8)>,
+    storage: StorageType,
+    hooks: ComponentHooks,
+    clone: Option<fn(*const u8, *mut u8)>,
+}
+
+impl ComponentInfo {
+    /// The id returned by `Component::__internal_id`.
+    pub fn id(&self) -> ComponentId {
+        self.id
+    }
+
+    /// Dense index assigned in registration order.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The type name without module paths, e.g. `Position`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The full type name, e.g. `game::physics::Position`.
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    pub fn drop_fn(&self) -> Option<fn(*mut u8)> {
+        self.drop
+    }
+
+    pub fn storage(&self) -> StorageType {
+        self.storage
+    }
+
+    pub fn hooks(&self) -> &ComponentHooks {
+        &self.hooks
+    }
+
+    /// Copies a value of this component, if it was registered as cloneable.
+    pub fn clone_fn(&self) -> Option<fn(*const u8, *mut u8)> {
+        self.clone
+    }
+}
+
+/// Where the values of a component live.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum StorageType {
+    /// Stored in a column of the entity's archetype table.
+    Table,
+}
+
+/// Strip module paths from a type name, including any generic arguments.
+pub fn short_name(full: &str) -> String {
+    fn last_segment(path: &str) -> &str {
+        path.rsplit("::").next().unwrap_or(path)
+    }
+
+    let mut out = String::with_capacity(full.len());
+    let mut start = 0;
+    for (i, c) in full.char_indices() {
+        if !(c.is_alphanumeric() || c == '_' || c == ':') {
+            out.push_str(last_segment(&full[start..i]));
+            out.push(c);
+            start = i + c.len_utf8();
+        }
+    }
+    out.push_str(last_segment(&full[start..]));
+    out
+}
+
+/// A stable id for a type defined inside strata, hashed from its path (FNV-1a).
+pub const fn id_of(path: &str) -> u64 {
+    let bytes = path.as_bytes();
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut i = 0;
+    while i < bytes.len() {
+        hash ^= bytes[i] as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+        i += 1;
+    }
+    hash
+}
+
+/// Implement `Component` for a type defined inside strata.
+macro_rules! impl_component {
+    ($t:ident) => {
+        impl strata_traits::Component for $t {
+            fn __internal_id() -> u64 {
+                $crate::components::id_of(concat!(module_path!(), "::", stringify!($t)))
+            }
+        }
+    };
+}
+
+pub(crate) use impl_component;
@@ -1,12 +1,28 @@
 
 use std::sync::Arc;
 
-use strata_traits::Resource;
+use strata_traits::{Component, Resource};
 
 use crate::resources::Resources;
 use crate::archetypes::Archetypes;
+use crate::components::Components;
+use crate::commands::Commands;
+use crate::entity::EntityId;
+use crate::hooks::HookEvent;
+use crate::observers::{self, Event, Observer, Observers};
+use crate::hierarchy::{self, Children, Parent};
+use crate::relations::{self, Relations};
+use crate::index::Indexes;
+use crate::registry::{EntityMap, TypeRegistry};
+use crate::snapshot::{self, SnapshotError};
+use crate::reflect::Reflect;
+use crate::clone::{self, CloneError};
+use crate::rollback::WorldState;
+use crate::checksum::{self, WorldChecksum};
+use crate::changes::Tick;
+use crate::entity::Entity;
 use crate::systems::Systems;
-use crate::scheduler::UnsafeRef;
+use crate::scheduler::{Scheduler, UnsafeRef};
 use crate::systems::Stage;
 use crate::systems::IntoSystem;
 
@@ -14,14 +30,42 @@ pub struct Engine {
     pub(crate) resources: Resources,
     pub(crate) archetypes: Archetypes,
     pub(crate) systems: Systems,
+    pub(crate) components: Components,
+    pub(crate) observers: Observers,
+    pub(crate) relations: Relations,
+    pub(crate) registry: TypeRegistry,
+    pub(crate) indexes: Indexes,
 }
 
 impl Engine {
     pub(crate) fn new() -> Self {
+        let mut components = Components::new();
+        let hooks = components.hooks_mut::<Observer>();
+        hooks.on_insert = Some(observers::on_insert_observer);
+        hooks.on_remove = Some(observers::on_remove_observer);
+        components.hooks_mut::<Parent>().on_remove = Some(hierarchy::on_remove_parent);
+        components.hooks_mut::<Children>().on_remove = Some(hierarchy::on_remove_children);
+        components.register_clone::<Observer>();
+        components.register_clone::<Parent>();
+        components.register_clone::<Children>();
+
+        let mut registry = TypeRegistry::new();
+        registry.register_component::<Parent>();
+        registry.register_component::<Children>();
+        registry.register_map_entities::<Parent>();
+        registry.register_map_entities::<Children>();
+        registry.register_hash::<Parent>();
+        registry.register_hash::<Children>();
+
         Self {
             resources: Resources::new(),
             archetypes: Archetypes::new(),
             systems: Systems::new(),
+            components,
+            observers: Observers::new(),
+            relations: Relations::new(),
+            registry,
+            indexes: Indexes::new(),
         }
     }
 
@@ -35,12 +79,357 @@ impl Engine {
     }
 
     pub fn execute_startup(&mut self) {
-        self.systems.execute_startup(UnsafeRef::new(&self));
-        self.archetypes.flush_queues();
+        // systems are taken out while they run, so flushing between 
+        // segments never has them borrowed.
+        let systems = std::mem::replace(&mut self.systems, Systems::new());
+        for scheduler in systems.startup() {
+            self.run_schedule(scheduler, systems.is_strict());
+        }
+        self.systems = systems;
     }
 
+    /// Run every stage. Commands are applied at the end of each stage,
+    /// so later stages see what earlier ones spawned.
     pub fn execute_systems(&mut self) {
-        self.systems.execute_systems(UnsafeRef::new(&self));
-        self.archetypes.flush_queues();
+        let systems = std::mem::replace(&mut self.systems, Systems::new());
+        for scheduler in systems.systems() {
+            self.run_schedule(scheduler, systems.is_strict());
+        }
+        self.systems = systems;
+        self.archetypes.end_frame();
+    }
+
+    /// Run each segment of `scheduler`, then queue the commands its systems buffered 
+    /// in system order and apply them, so later segments see them. Commands are 
+    /// applied at the end of the stage even if it has no systems.
+    fn run_schedule(&mut self, scheduler: &Scheduler, strict: bool) {
+        let segments = scheduler.segments();
+        if segments.is_empty() {
+            self.flush();
+        }
+
+        for segment in segments {
+            scheduler.execute(segment, UnsafeRef::new(&*self), strict);
+            self.archetypes.queue_buffered();
+            self.flush();
+        }
+    }
+
+    /// Apply all queued commands, running component hooks as they happen.
+    /// Commands queued by hooks are applied too, until nothing is left.
+    pub(crate) fn flush(&mut self) {
+        let mut events = Vec::new();
+        let mut despawned = Vec::new();
+        loop {
+            // removal hooks run first, while the data still exists.
+            self.archetypes.pending_removals(&self.components, &mut events);
+            let removed = self.run_hooks(&mut events);
+
+            // relations pointing at destroyed entities are cleaned up next pass
+            self.archetypes.pending_destroys(&mut despawned);
+            let cleanup = relations::cleanup(self, &despawned);
+            despawned.clear();
+
+            self.archetypes.flush_queues(&mut self.components, &mut events);
+            let added = self.run_hooks(&mut events);
+
+            // queue what the hooks deferred
+            drop(removed);
+            drop(cleanup);
+            drop(added);
+
+            // run deferred work like triggers and user commands, in the order it was queued
+            for deferred in self.archetypes.take_deferred() {
+                deferred(self);
+            }
+
+            if !self.archetypes.is_dirty() {
+                break;
+            }
+        }
+    }
+
+    fn run_hooks(&mut self, events: &mut Vec<HookEvent>) -> Commands {
+        let mut commands = Commands::new(UnsafeRef::new(&*self));
+        for event in events.drain(..) {
+            if let Some(hook) = self.components.hook(event.component, event.kind) {
+                hook(self, event.entity, &mut commands);
+            }
+        }
+        commands
+    }
+
+    /// The components that were inserted into entities that had already been 
+    /// destroyed, with their names, since the last call. Those inserts are dropped.
+    pub fn take_dropped_inserts(&mut self) -> Vec<(EntityId, String)> {
+        self.archetypes.take_dropped().into_iter()
+            .map(|(entity, id)| (entity, self.components.name(id)))
+            .collect()
+    }
+
+    /// Commands that are queued when dropped and applied at the next flush.
+    pub fn commands(&self) -> Commands {
+        Commands::new(UnsafeRef::new(self))
+    }
+
+    /// Run every global observer of `E` immediately.
+    pub fn trigger<E: Event>(&mut self, event: E) {
+        observers::trigger(self, event, None);
+    }
+
+    /// Run the observers of `E` watching `target` immediately, 
+    /// as well as every global observer of `E`.
+    pub fn trigger_targets<E: Event>(&mut self, target: EntityId, event: E) {
+        observers::trigger(self, event, Some(target));
+    }
+
+    /// Spawn an entity immediately, flushing anything else that was queued.
+    pub fn spawn<F>(&mut self, predicate: F) -> EntityId
+    where
+        F: Fn(&mut Entity)
+    {
+        let id = self.commands().spawn(predicate).id();
+        self.flush();
+        id
+    }
+
+    /// Insert a component immediately, flushing anything else that was queued.
+    pub fn insert<C: Component>(&mut self, entity: EntityId, cmp: C) {
+        self.commands().insert(entity, cmp);
+        self.flush();
+    }
+
+    /// Remove a component immediately, flushing anything else that was queued.
+    pub fn remove<C: Component>(&mut self, entity: EntityId) {
+        self.commands().remove::<C>(entity);
+        self.flush();
+    }
+
+    /// Destroy an entity immediately, flushing anything else that was queued.
+    pub fn despawn(&mut self, entity: EntityId) {
+        self.commands().destroy(entity);
+        self.flush();
+    }
+
+    /// Spawn a copy of `entity` immediately, with the same parent but no children.
+    /// Fails, listing them, if any of its components were not registered as cloneable.
+    pub fn clone_entity(&mut self, entity: EntityId) -> Result<EntityId, CloneError> {
+        let target = self.archetypes.reserve();
+        clone::clone_into(self, entity, target)?;
+        self.flush();
+        Ok(target)
+    }
+
+    /// A deep copy of the engine, e.g. to simulate ahead for rollback netcode. Systems are
+    /// shared, so they must be stateless, and only resources registered with
+    /// `EngineBuilder::register_rollback_resource` are copied. Fails if any stored
+    /// component was not registered as cloneable.
+    pub fn fork(&mut self) -> Result<Engine, CloneError> {
+        self.flush();
+        self.check_cloneable()?;
+
+        let mut resources = Resources::new();
+        for (id, fns) in self.registry.rollback() {
+            if self.resources.contains(id) {
+                (fns.fork)(&self.resources, &mut resources);
+            }
+        }
+
+        Ok(Self {
+            resources,
+            archetypes: self.archetypes.fork(&self.components),
+            systems: self.systems.clone(),
+            components: self.components.clone(),
+            observers: self.observers.clone(),
+            relations: self.relations.clone(),
+            registry: self.registry.clone(),
+            indexes: self.indexes.clone(),
+        })
+    }
+
+    /// Copy every entity and rollback resource into `state`, reusing the memory of
+    /// whatever it held before. Queued commands are applied first. If any stored
+    /// component is not cloneable, `state` is left untouched.
+    pub fn save_state(&mut self, state: &mut WorldState) -> Result<(), CloneError> {
+        self.flush();
+        self.check_cloneable()?;
+
+        self.archetypes.save(&self.components, &mut state.tables, &mut state.entities);
+        for (id, fns) in self.registry.rollback() {
+            if self.resources.contains(id) {
+                (fns.save)(&self.resources, state.resources.entry(id).or_default());
+            } else {
+                state.resources.remove(&id);
+            }
+        }
+        state.relations.clone_from(&self.relations);
+        state.observers.clone_from(&self.observers);
+        state.indexes.clone_from(&self.indexes);
+        Ok(())
+    }
+
+    /// Put the engine back into a state saved from it by `save_state`. Entities spawned
+    /// since are gone and destroyed ones are back, with the same ids. Hooks don't run.
+    pub fn load_state(&mut self, state: &WorldState) {
+        self.flush();
+
+        self.archetypes.load(&self.components, &state.tables, &state.entities);
+        for (id, fns) in self.registry.rollback() {
+            if let Some(Some(saved)) = state.resources.get(&id) {
+                if self.resources.contains(id) {
+                    (fns.load)(&mut self.resources, saved.as_ref());
+                }
+            }
+        }
+        self.relations.clone_from(&state.relations);
+        self.observers.clone_from(&state.observers);
+        self.indexes.clone_from(&state.indexes);
+    }
+
+    fn check_cloneable(&self) -> Result<(), CloneError> {
+        let missing = self.archetypes.not_cloneable(&self.components);
+        if missing.is_empty() { Ok(()) } else { Err(CloneError::NotCloneable(missing)) }
+    }
+
+    /// Reverse lookups for every registered relation.
+    pub fn relations(&self) -> &Relations {
+        &self.relations
+    }
+
+    /// Lookups by value for every indexed component.
+    pub fn indexes(&self) -> &Indexes {
+        &self.indexes
+    }
+
+    pub fn contains(&self, entity: EntityId) -> bool {
+        self.archetypes.contains(entity)
+    }
+
+    pub fn get<C: Component>(&self, entity: EntityId) -> Option<&C> {
+        self.archetypes.get::<C>(entity)
+    }
+
+    /// A component, marked as changed.
+    pub fn get_mut<C: Component>(&mut self, entity: EntityId) -> Option<&mut C> {
+        self.archetypes.get_mut::<C>(entity)
+    }
+
+    /// Returns the current tick and moves on to the next one. Pass the returned 
+    /// tick as `since` next time to see only what changed after this call.
+    pub fn advance_tick(&self) -> Tick {
+        self.archetypes.tick().advance()
+    }
+
+    /// The tick `C` was last inserted into or changed on `entity` at.
+    pub fn last_changed<C: Component>(&self, entity: EntityId) -> Option<Tick> {
+        self.archetypes.last_changed::<C>(entity)
+    }
+
+    /// Every entity whose `C` was inserted or changed after `since`, in table order.
+    pub fn changed<C: Component>(&self, since: Tick) -> Vec<EntityId> {
+        let mut out = Vec::new();
+        for table in self.archetypes.tables() {
+            if let Some(ticks) = table.ticks(C::__internal_id()) {
+                out.extend(table.ids().iter().zip(ticks).filter(|(_, tick)| **tick > since).map(|(id, _)| *id));
+            }
+        }
+        out
+    }
+
+    /// Every entity `C` was removed from after `since`, including despawned ones. 
+    /// Removals are only kept for two frames, so read them at least once a frame.
+    pub fn removed<C: Component>(&self, since: Tick) -> impl Iterator<Item = EntityId> + '_ {
+        self.archetypes.removals().removed(C::__internal_id(), since)
+    }
+
+    /// Every entity despawned after `since`. Kept for two frames, like `removed`.
+    pub fn despawned(&self, since: Tick) -> impl Iterator<Item = EntityId> + '_ {
+        self.archetypes.removals().despawned(since)
+    }
+
+    pub fn resource<R: Resource>(&self) -> &R {
+        unsafe { self.resources.get::<R>() }
+    }
+
+    pub fn resource_mut<R: Resource>(&mut self) -> &mut R {
+        unsafe { self.resources.get::<R>() }
+    }
+
+    /// Every component the engine has registered or stored so far.
+    pub fn components(&self) -> &Components {
+        &self.components
+    }
+
+    /// Serialization functions for the components and resources that opted in.
+    pub fn registry(&self) -> &TypeRegistry {
+        &self.registry
+    }
+
+    /// Write every entity with its serializable components, and every serializable 
+    /// resource, to a versioned binary snapshot. Queued commands and entities without 
+    /// serializable components, like observers, are not included.
+    pub fn save_snapshot(&self) -> Result<Vec<u8>, SnapshotError> {
+        snapshot::save(self)
+    }
+
+    /// Spawn the entities in a snapshot as new entities and overwrite its resources,
+    /// remapping the entity ids stored in components. Returns the saved ids mapped to the new ones.
+    pub fn load_snapshot(&mut self, bytes: &[u8]) -> Result<EntityMap, SnapshotError> {
+        snapshot::load(self, bytes)
+    }
+
+    /// A hash of every component registered with `EngineBuilder::register_hash`, which is
+    /// the same for equal worlds no matter the order entities were spawned or moved in.
+    pub fn checksum(&self) -> WorldChecksum {
+        checksum::checksum(self)
+    }
+
+    /// A component of `entity` by its short or full name, if it was registered with
+    /// `EngineBuilder::register_reflect`.
+    pub fn reflect(&self, entity: EntityId, component: &str) -> Option<&dyn Reflect> {
+        let id = self.components.get_by_name(component)?.id();
+        let reflect = self.registry.reflect(id)?;
+        let (column, col) = self.archetypes.column(entity, id)?;
+        Some((reflect.get)(column, col))
+    }
+
+    /// Like `reflect`, but marks the component as changed.
+    pub fn reflect_mut(&mut self, entity: EntityId, component: &str) -> Option<&mut dyn Reflect> {
+        let id = self.components.get_by_name(component)?.id();
+        let reflect = self.registry.reflect(id)?;
+        let (column, col) = self.archetypes.column_mut(entity, id)?;
+        Some((reflect.get_mut)(column, col))
+    }
+
+    /// Every reflected component of `entity`, with its name.
+    pub fn reflect_all(&self, entity: EntityId) -> Vec<(&str, &dyn Reflect)> {
+        let mut out = Vec::new();
+        if let Some(index) = self.archetypes.locate(entity) {
+            for id in self.archetypes.tables()[index.table].archetype().ids() {
+                if let (Some(info), Some(reflect)) = (self.components.get(*id), self.registry.reflect(*id)) {
+                    if let Some((column, col)) = self.archetypes.column(entity, *id) {
+                        out.push((info.name(), (reflect.get)(column, col)));
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    /// A resource by its short or full type name, if it was registered with
+    /// `EngineBuilder::register_reflect_resource`.
+    pub fn reflect_resource(&self, name: &str) -> Option<&dyn Reflect> {
+        let (id, reflect) = self.registry.reflect_resource(name)?;
+        self.resources.contains(id).then(|| (reflect.get)(&self.resources))
+    }
+
+    pub fn reflect_resource_mut(&mut self, name: &str) -> Option<&mut dyn Reflect> {
+        let (id, reflect) = self.registry.reflect_resource(name)?;
+        self.resources.contains(id).then(|| (reflect.get_mut)(&mut self.resources))
+    }
+
+    /// A human-readable listing of every table and the components in it.
+    pub fn debug_dump(&self) -> String {
+        self.archetypes.describe(&self.components)
     }
 }
\ No newline at end of file
@@ -1,25 +1,36 @@
+use std::fmt;
 
 use crate::archetypes::Archetype;
 use crate::archetypes::ComponentId;
 use crate::anon::Anon;
 use crate::archetypes::{Column, TableIndex};
+use crate::bundle::Bundle;
 
+use serde::{Deserialize, Serialize};
 use strata_traits::Component;
 
 pub struct Entity {
+    pub(crate) id: EntityId,
     pub(crate) components: Vec<Anon>,
     pub(crate) archetype: Archetype
 }
 
 impl Entity {
-    pub(crate) fn new() -> Self {
+    pub(crate) fn new(id: EntityId) -> Self {
         Self {
+            id,
             components: Vec::new(),
             archetype: Archetype::new()
         }
     }
 
+    /// The id this entity will have once it is spawned.
+    pub fn id(&self) -> EntityId {
+        self.id
+    }
+
     pub(crate) fn hash(&mut self) {
+        self.archetype.clear();
         for anon in self.components.iter() {
             self.archetype.add(anon.id())
         }
@@ -38,6 +49,7 @@ impl Entity {
     pub(crate) fn insert_anon(&mut self, anon: Anon) {
         for i in 0..self.components.len() {
             if anon.id() == self.components[i].id() {
+                self.components[i].clear();
                 self.components[i] = anon;
                 return;
             }
@@ -50,8 +62,209 @@ impl Entity {
         self.components.pop()
     }
 
+    /// Add a component, replacing the one of the same type if it has one.
     pub fn insert<C: Component>(&mut self, cmp: C) {
-        self.components.push(Anon::new::<C>(cmp));
+        self.insert_anon(Anon::new::<C>(cmp));
+    }
+
+    pub fn insert_bundle<B: Bundle>(&mut self, bundle: B) {
+        let mut anons = Vec::new();
+        bundle.into_anons(&mut anons);
+        for anon in anons {
+            self.insert_anon(anon);
+        }
+    }
+}
+
+/// A stable handle to an entity, valid for as long as the entity is alive 
+/// no matter which table it is stored in. Serialized as `to_bits`.
+#[derive(Copy, Clone, PartialEq, Eq, Ord, PartialOrd, Hash, Debug, Serialize, Deserialize)]
+#[serde(from = "u64", into = "u64")]
+pub struct EntityId {
+    index: u32,
+    generation: u32,
+}
+
+impl EntityId {
+    /// An id that is never given to an entity.
+    pub const DANGLING: EntityId = EntityId::new(u32::MAX, u32::MAX);
+
+    pub(crate) const fn new(index: u32, generation: u32) -> Self {
+        Self { index, generation }
+    }
+
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Incremented each time the index is reused, so stale ids are never alive.
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    pub fn to_bits(&self) -> u64 {
+        (self.generation as u64) << 32 | self.index as u64
+    }
+
+    pub fn from_bits(bits: u64) -> Self {
+        Self {
+            index: bits as u32,
+            generation: (bits >> 32) as u32,
+        }
+    }
+}
+
+impl From<u64> for EntityId {
+    fn from(bits: u64) -> Self {
+        EntityId::from_bits(bits)
+    }
+}
+
+impl From<EntityId> for u64 {
+    fn from(id: EntityId) -> Self {
+        id.to_bits()
+    }
+}
+
+/// Why an id assigned outside the engine could not be claimed.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ClaimError {
+    /// The index is used by a living entity with a different generation.
+    Occupied(EntityId),
+    /// The index is `MAX_INDEX` or above, e.g. `EntityId::DANGLING`.
+    OutOfRange(EntityId),
+}
+
+impl fmt::Display for ClaimError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClaimError::Occupied(id) => write!(f, "the index of {:?} is used by a living entity", id),
+            ClaimError::OutOfRange(id) => write!(f, "the index of {:?} is out of range", id),
+        }
+    }
+}
+
+impl std::error::Error for ClaimError {}
+
+/// Allocates entity ids and tracks where each living entity is stored.
+#[derive(Clone)]
+pub struct Entities {
+    meta: Vec<EntityMeta>,
+    free: Vec<u32>,
+    len: usize,
+}
+
+#[derive(Clone)]
+struct EntityMeta {
+    generation: u32,
+    alive: bool,
+    location: Option<EntityIndex>,
+}
+
+impl Entities {
+    /// Claimed indices must be below this, so a bad id can't make
+    /// the allocator grow to billions of entries.
+    pub const MAX_INDEX: u32 = 1 << 24;
+
+    pub fn new() -> Self {
+        Self {
+            meta: Vec::new(),
+            free: Vec::new(),
+            len: 0,
+        }
+    }
+
+    /// Reserve a new id. It has no location until it is spawned into a table.
+    pub fn alloc(&mut self) -> EntityId {
+        self.len += 1;
+        if let Some(index) = self.free.pop() {
+            let meta = &mut self.meta[index as usize];
+            meta.alive = true;
+            meta.location = None;
+            EntityId::new(index, meta.generation)
+        } else {
+            let index = self.meta.len() as u32;
+            self.meta.push(EntityMeta {
+                generation: 0,
+                alive: true,
+                location: None,
+            });
+            EntityId::new(index, 0)
+        }
+    }
+
+    /// Release an id so its index can be reused. Returns false if it was not alive.
+    pub fn free(&mut self, id: EntityId) -> bool {
+        if !self.contains(id) {
+            return false;
+        }
+
+        let meta = &mut self.meta[id.index as usize];
+        meta.generation = meta.generation.wrapping_add(1);
+        meta.alive = false;
+        meta.location = None;
+        self.free.push(id.index);
+        self.len -= 1;
+        true
+    }
+
+    pub fn contains(&self, id: EntityId) -> bool {
+        match self.meta.get(id.index as usize) {
+            Some(meta) => meta.alive && meta.generation == id.generation,
+            None => false,
+        }
+    }
+
+    /// Where the entity is stored, if it is alive and has been spawned.
+    pub fn get(&self, id: EntityId) -> Option<EntityIndex> {
+        if self.contains(id) {
+            self.meta[id.index as usize].location
+        } else {
+            None
+        }
+    }
+
+    /// Make exactly `id` alive, for ids assigned outside this engine.
+    /// Returns false if it is already alive.
+    pub fn alloc_at(&mut self, id: EntityId) -> Result<bool, ClaimError> {
+        if self.contains(id) {
+            return Ok(false);
+        }
+        if id.index >= Self::MAX_INDEX {
+            return Err(ClaimError::OutOfRange(id));
+        }
+
+        let index = id.index as usize;
+        while self.meta.len() <= index {
+            self.free.push(self.meta.len() as u32);
+            self.meta.push(EntityMeta {
+                generation: 0,
+                alive: false,
+                location: None,
+            });
+        }
+
+        let meta = &mut self.meta[index];
+        if meta.alive {
+            return Err(ClaimError::Occupied(id));
+        }
+        meta.generation = id.generation;
+        meta.alive = true;
+        meta.location = None;
+        self.free.retain(|i| *i != id.index);
+        self.len += 1;
+        Ok(true)
+    }
+
+    pub fn set(&mut self, id: EntityId, location: EntityIndex) {
+        if self.contains(id) {
+            self.meta[id.index as usize].location = Some(location);
+        }
+    }
+
+    /// The number of living entities, including ones waiting to be spawned.
+    pub fn len(&self) -> usize {
+        self.len
     }
 }
 
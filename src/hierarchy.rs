@@ -0,0 +1,257 @@
+use serde::{Deserialize, Serialize};
+
+use crate::checksum::{StableHash, StableHasher};
+use crate::commands::{Commands, EntityCommands};
+use crate::components::impl_component;
+use crate::engine::Engine;
+use crate::entity::{Entity, EntityId};
+use crate::registry::{EntityMap, MapEntities};
+
+/// The parent of an entity. Use `EntityCommands::set_parent` to change it
+/// so the parent's `Children` stay in sync.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Parent(pub(crate) EntityId);
+
+impl_component!(Parent);
+
+impl Parent {
+    pub fn get(&self) -> EntityId {
+        self.0
+    }
+}
+
+/// The children of an entity, in the order they were added.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Children(pub(crate) Vec<EntityId>);
+
+impl_component!(Children);
+
+impl Children {
+    pub fn iter(&self) -> std::slice::Iter<'_, EntityId> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn contains(&self, entity: EntityId) -> bool {
+        self.0.contains(&entity)
+    }
+}
+
+impl MapEntities for Parent {
+    fn map_entities(&mut self, map: &EntityMap) {
+        self.0 = map.map(self.0);
+    }
+}
+
+impl MapEntities for Children {
+    fn map_entities(&mut self, map: &EntityMap) {
+        for child in self.0.iter_mut() {
+            *child = map.map(*child);
+        }
+    }
+}
+
+impl StableHash for Parent {
+    fn stable_hash(&self, hasher: &mut StableHasher) {
+        self.0.stable_hash(hasher);
+    }
+}
+
+impl StableHash for Children {
+    fn stable_hash(&self, hasher: &mut StableHasher) {
+        self.0.stable_hash(hasher);
+    }
+}
+
+/// Spawns entities as children of `parent`.
+pub struct ChildBuilder<'a> {
+    commands: &'a mut Commands,
+    parent: EntityId,
+    children: Vec<EntityId>,
+}
+
+impl<'a> ChildBuilder<'a> {
+    pub fn spawn<F>(&mut self, predicate: F) -> EntityId
+    where
+        F: Fn(&mut Entity)
+    {
+        let parent = self.parent;
+        let id = self.commands.spawn(|entity| {
+            predicate(entity);
+            entity.insert(Parent(parent));
+        }).id();
+        self.children.push(id);
+        id
+    }
+
+    pub fn parent(&self) -> EntityId {
+        self.parent
+    }
+}
+
+impl<'a> EntityCommands<'a> {
+    /// Spawn children of this entity.
+    pub fn with_children<F>(&mut self, spawn: F) -> &mut Self
+    where
+        F: FnOnce(&mut ChildBuilder)
+    {
+        let parent = self.id();
+        let mut builder = ChildBuilder {
+            commands: self.commands(),
+            parent,
+            children: Vec::new(),
+        };
+        spawn(&mut builder);
+
+        let children = builder.children;
+        self.commands().defer(move |engine| {
+            for child in children {
+                add_child(engine, parent, child);
+            }
+        });
+        self
+    }
+
+    /// Make this entity a child of `parent`, detaching it from its current parent.
+    pub fn set_parent(&mut self, parent: EntityId) -> &mut Self {
+        let child = self.id();
+        self.commands().defer(move |engine| set_parent(engine, child, parent));
+        self
+    }
+
+    /// Detach this entity from its parent, if it has one.
+    pub fn remove_parent(&mut self) -> &mut Self {
+        let child = self.id();
+        self.commands().remove::<Parent>(child);
+        self
+    }
+
+    /// Destroy this entity and all of its descendants.
+    pub fn despawn_recursive(&mut self) {
+        let entity = self.id();
+        self.commands().defer(move |engine| despawn_recursive(engine, entity));
+    }
+}
+
+/// The parent of `entity`, for use as `Event::traverse` to bubble triggers up the hierarchy.
+pub fn parent_of(engine: &Engine, entity: EntityId) -> Option<EntityId> {
+    engine.get::<Parent>(entity).map(|parent| parent.0)
+}
+
+/// True if `ancestor` is `entity` or one of its ancestors.
+pub fn is_ancestor(engine: &Engine, ancestor: EntityId, entity: EntityId) -> bool {
+    let mut current = Some(entity);
+    while let Some(e) = current {
+        if e == ancestor {
+            return true;
+        }
+        current = parent_of(engine, e);
+    }
+    false
+}
+
+// these run as deferred work inside a flush, so they change `Parent` and `Children` in
+// place where they exist and queue inserts where they don't. An insert queued earlier in
+// the same flush is changed through `queued_mut` instead, so it isn't replaced.
+
+/// The `Children` of `parent`, including one queued to be inserted by this flush.
+fn children_mut(engine: &mut Engine, parent: EntityId) -> Option<&mut Children> {
+    if engine.archetypes.queued_mut::<Children>(parent).is_some() {
+        return engine.archetypes.queued_mut::<Children>(parent);
+    }
+    engine.get_mut::<Children>(parent)
+}
+
+/// The parent of `entity`, including one queued to be inserted by this flush.
+fn pending_parent(engine: &mut Engine, entity: EntityId) -> Option<EntityId> {
+    match engine.archetypes.queued_mut::<Parent>(entity) {
+        Some(parent) => Some(parent.0),
+        None => parent_of(engine, entity),
+    }
+}
+
+pub(crate) fn add_child(engine: &mut Engine, parent: EntityId, child: EntityId) {
+    if !engine.contains(parent) || !engine.contains(child) {
+        return;
+    }
+
+    match children_mut(engine, parent) {
+        Some(children) => {
+            if !children.0.contains(&child) {
+                children.0.push(child);
+            }
+        }
+        None => engine.commands().insert(parent, Children(vec![child])),
+    }
+}
+
+pub(crate) fn set_parent(engine: &mut Engine, child: EntityId, parent: EntityId) {
+    if !engine.contains(parent) || !engine.contains(child) {
+        return;
+    }
+
+    // refuse to create cycles, counting parents set earlier in this flush
+    let mut current = Some(parent);
+    while let Some(e) = current {
+        if e == child {
+            return;
+        }
+        current = pending_parent(engine, e);
+    }
+
+    if let Some(old) = pending_parent(engine, child) {
+        if old == parent {
+            return;
+        }
+        if let Some(children) = children_mut(engine, old) {
+            children.0.retain(|c| *c != child);
+        }
+    }
+
+    if let Some(queued) = engine.archetypes.queued_mut::<Parent>(child) {
+        queued.0 = parent;
+    } else if let Some(current) = engine.get_mut::<Parent>(child) {
+        current.0 = parent;
+    } else {
+        engine.commands().insert(child, Parent(parent));
+    }
+    add_child(engine, parent, child);
+}
+
+fn despawn_recursive(engine: &mut Engine, entity: EntityId) {
+    let mut stack = vec![entity];
+    let mut commands = engine.commands();
+    while let Some(e) = stack.pop() {
+        if let Some(children) = engine.get::<Children>(e) {
+            stack.extend(children.iter().copied());
+        }
+        commands.destroy(e);
+    }
+}
+
+/// Detach a child from its parent's `Children` before `Parent` is removed.
+pub(crate) fn on_remove_parent(engine: &mut Engine, entity: EntityId, _: &mut Commands) {
+    if let Some(parent) = parent_of(engine, entity) {
+        if let Some(children) = engine.get_mut::<Children>(parent) {
+            children.0.retain(|c| *c != entity);
+        }
+    }
+}
+
+/// Orphan the children of an entity losing its `Children`.
+pub(crate) fn on_remove_children(engine: &mut Engine, entity: EntityId, commands: &mut Commands) {
+    if let Some(children) = engine.get::<Children>(entity) {
+        for child in children.iter() {
+            if parent_of(engine, *child) == Some(entity) {
+                commands.remove::<Parent>(*child);
+            }
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use crate::archetypes::ComponentId;
+use crate::commands::Commands;
+use crate::engine::Engine;
+use crate::entity::EntityId;
+
+/// A function run when a component is added to, inserted on or removed from an entity.
+/// Structural changes have to go through the deferred `Commands`.
+pub type ComponentHook = fn(&mut Engine, EntityId, &mut Commands);
+
+/// The hooks registered for a single component type.
+#[derive(Copy, Clone, Default)]
+pub struct ComponentHooks {
+    /// Runs when the component is added to an entity that did not have it.
+    pub on_add: Option<ComponentHook>,
+    /// Runs every time the component is inserted, including when it
+    /// is added and when it replaces an existing value.
+    pub on_insert: Option<ComponentHook>,
+    /// Runs before the component is removed or its entity is destroyed,
+    /// while the value can still be read.
+    pub on_remove: Option<ComponentHook>,
+}
+
+impl ComponentHooks {
+    pub fn get(&self, kind: HookKind) -> Option<ComponentHook> {
+        match kind {
+            HookKind::OnAdd => self.on_add,
+            HookKind::OnInsert => self.on_insert,
+            HookKind::OnRemove => self.on_remove,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum HookKind {
+    OnAdd,
+    OnInsert,
+    OnRemove,
+}
+
+/// A hook waiting to run, recorded while the queues are flushed.
+#[derive(Copy, Clone)]
+pub struct HookEvent {
+    pub kind: HookKind,
+    pub component: ComponentId,
+    pub entity: EntityId,
+}
+
+impl HookEvent {
+    pub fn new(kind: HookKind, component: ComponentId, entity: EntityId) -> Self {
+        Self { kind, component, entity }
+    }
+}
@@ -0,0 +1,418 @@
+use std::any::{type_name, Any};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+
+use strata_traits::Component;
+
+use crate::archetypes::ComponentId;
+use crate::changes::{Tick, Ticks};
+use crate::commands::Commands;
+use crate::engine::Engine;
+use crate::entity::EntityId;
+use crate::hooks::ComponentHooks;
+use crate::query::{Query, Ref};
+use crate::scheduler::{Accessor, SystemIndex, UnsafeRef};
+use crate::systems::SystemParam;
+
+/// Lookups of entities by component value, for every component registered with
+/// `EngineBuilder::register_hash_index` or `register_ordered_index`. Inserts and removals
+/// are indexed by the component's hooks, and values edited in place through `Mut<C>`
+/// by `update_index` at the end of every frame.
+pub struct Indexes {
+    kinds: HashMap<ComponentId, Box<dyn AnyIndex>>,
+}
+
+impl Clone for Indexes {
+    fn clone(&self) -> Self {
+        Self {
+            kinds: self.kinds.iter().map(|(id, index)| (*id, index.clone_box())).collect(),
+        }
+    }
+}
+
+impl Indexes {
+    pub fn new() -> Self {
+        Self {
+            kinds: HashMap::new(),
+        }
+    }
+
+    /// Returns false if `C` already has an index.
+    pub(crate) fn register_hash<C: Component + Hash + Eq + Clone>(&mut self) -> bool {
+        self.register(ComponentIndex::<C>::new(Box::new(HashStore::<C>(HashMap::new()))))
+    }
+
+    /// Returns false if `C` already has an index.
+    pub(crate) fn register_ordered<C: Component + Ord + Clone>(&mut self) -> bool {
+        self.register(ComponentIndex::<C>::new(Box::new(OrderedStore::<C>(BTreeMap::new()))))
+    }
+
+    fn register<C: Component + Clone>(&mut self, index: ComponentIndex<C>) -> bool {
+        if self.kinds.contains_key(&C::__internal_id()) {
+            return false;
+        }
+        self.kinds.insert(C::__internal_id(), Box::new(index));
+        true
+    }
+
+    /// The hooks of an indexed component, which run after the index is updated.
+    pub(crate) fn chained_mut(&mut self, id: ComponentId) -> Option<&mut ComponentHooks> {
+        self.kinds.get_mut(&id).map(|index| index.chained_mut())
+    }
+
+    /// Every entity whose `C` equals `value`, in the order they got it.
+    pub fn get<C: Component + Clone>(&self, value: &C) -> &[EntityId] {
+        self.index::<C>().store.get(value)
+    }
+
+    /// Every entity whose `C` is in `range`, ordered by value.
+    /// Panics if `C` has a hash index rather than an ordered one.
+    pub fn range<C, R>(&self, range: R) -> impl Iterator<Item = EntityId> + '_
+    where
+        C: Component + Clone,
+        R: RangeBounds<C>,
+    {
+        let bounds = (range.start_bound().cloned(), range.end_bound().cloned());
+        match self.index::<C>().store.range(bounds) {
+            Some(iter) => iter,
+            None => panic!("Attempted to get a range of {}, but it has a hash index!", type_name::<C>()),
+        }
+    }
+
+    /// The value of `C` that `entity` is indexed under.
+    pub fn value<C: Component + Clone>(&self, entity: EntityId) -> Option<&C> {
+        self.index::<C>().values.get(&entity)
+    }
+
+    fn index<C: Component + Clone>(&self) -> &ComponentIndex<C> {
+        self.kinds.get(&C::__internal_id())
+            .and_then(|index| index.as_any().downcast_ref::<ComponentIndex<C>>())
+            .unwrap_or_else(|| panic!("Attempted to look up {} by value, but it has no index!", type_name::<C>()))
+    }
+
+    fn index_mut<C: Component + Clone>(&mut self) -> Option<&mut ComponentIndex<C>> {
+        self.kinds.get_mut(&C::__internal_id())
+            .and_then(|index| index.as_any_mut().downcast_mut::<ComponentIndex<C>>())
+    }
+}
+
+trait AnyIndex: Send + Sync {
+    fn clone_box(&self) -> Box<dyn AnyIndex>;
+    fn chained_mut(&mut self) -> &mut ComponentHooks;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+struct ComponentIndex<C> {
+    store: Box<dyn Store<C>>,
+    /// entity -> the value it is stored under, to find it again when it changes
+    values: HashMap<EntityId, C>,
+    /// the hooks `C` had before it was indexed or was given after
+    chained: ComponentHooks,
+    /// the tick of the last `update_index`
+    tick: Tick,
+}
+
+impl<C: Component + Clone> ComponentIndex<C> {
+    fn new(store: Box<dyn Store<C>>) -> Self {
+        Self {
+            store,
+            values: HashMap::new(),
+            chained: ComponentHooks::default(),
+            tick: 0,
+        }
+    }
+
+    fn insert(&mut self, entity: EntityId, value: C) {
+        self.remove(entity);
+        self.store.insert(value.clone(), entity);
+        self.values.insert(entity, value);
+    }
+
+    fn remove(&mut self, entity: EntityId) {
+        if let Some(old) = self.values.remove(&entity) {
+            self.store.remove(&old, entity);
+        }
+    }
+}
+
+impl<C: Component + Clone> AnyIndex for ComponentIndex<C> {
+    fn clone_box(&self) -> Box<dyn AnyIndex> {
+        Box::new(Self {
+            store: self.store.clone_box(),
+            values: self.values.clone(),
+            chained: self.chained,
+            tick: self.tick,
+        })
+    }
+
+    fn chained_mut(&mut self) -> &mut ComponentHooks {
+        &mut self.chained
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Value -> entities, either hashed or ordered.
+trait Store<C>: Send + Sync {
+    fn insert(&mut self, value: C, entity: EntityId);
+    fn remove(&mut self, value: &C, entity: EntityId);
+    fn get(&self, value: &C) -> &[EntityId];
+    /// `None` if this store isn't ordered.
+    fn range(&self, bounds: (Bound<C>, Bound<C>)) -> Option<Box<dyn Iterator<Item = EntityId> + '_>>;
+    fn clone_box(&self) -> Box<dyn Store<C>>;
+}
+
+struct HashStore<C>(HashMap<C, Vec<EntityId>>);
+
+impl<C: Component + Hash + Eq + Clone> Store<C> for HashStore<C> {
+    fn insert(&mut self, value: C, entity: EntityId) {
+        self.0.entry(value).or_default().push(entity);
+    }
+
+    fn remove(&mut self, value: &C, entity: EntityId) {
+        if let Some(entities) = self.0.get_mut(value) {
+            entities.retain(|e| *e != entity);
+            if entities.is_empty() {
+                self.0.remove(value);
+            }
+        }
+    }
+
+    fn get(&self, value: &C) -> &[EntityId] {
+        self.0.get(value).map(|entities| entities.as_slice()).unwrap_or(&[])
+    }
+
+    fn range(&self, _: (Bound<C>, Bound<C>)) -> Option<Box<dyn Iterator<Item = EntityId> + '_>> {
+        None
+    }
+
+    fn clone_box(&self) -> Box<dyn Store<C>> {
+        Box::new(HashStore(self.0.clone()))
+    }
+}
+
+struct OrderedStore<C>(BTreeMap<C, Vec<EntityId>>);
+
+impl<C: Component + Ord + Clone> Store<C> for OrderedStore<C> {
+    fn insert(&mut self, value: C, entity: EntityId) {
+        self.0.entry(value).or_default().push(entity);
+    }
+
+    fn remove(&mut self, value: &C, entity: EntityId) {
+        if let Some(entities) = self.0.get_mut(value) {
+            entities.retain(|e| *e != entity);
+            if entities.is_empty() {
+                self.0.remove(value);
+            }
+        }
+    }
+
+    fn get(&self, value: &C) -> &[EntityId] {
+        self.0.get(value).map(|entities| entities.as_slice()).unwrap_or(&[])
+    }
+
+    fn range(&self, bounds: (Bound<C>, Bound<C>)) -> Option<Box<dyn Iterator<Item = EntityId> + '_>> {
+        // `BTreeMap::range` panics on inverted ranges instead of returning nothing
+        let inverted = match (&bounds.0, &bounds.1) {
+            (Bound::Excluded(start), Bound::Excluded(end)) => start >= end,
+            (Bound::Included(start) | Bound::Excluded(start), Bound::Included(end) | Bound::Excluded(end)) => start > end,
+            _ => false,
+        };
+        if inverted {
+            return Some(Box::new(std::iter::empty()));
+        }
+        Some(Box::new(self.0.range(bounds).flat_map(|(_, entities)| entities.iter().copied())))
+    }
+
+    fn clone_box(&self) -> Box<dyn Store<C>> {
+        Box::new(OrderedStore(self.0.clone()))
+    }
+}
+
+pub(crate) fn on_insert_index<C: Component + Clone>(engine: &mut Engine, entity: EntityId, commands: &mut Commands) {
+    let value = engine.archetypes.get::<C>(entity).cloned();
+    let Some(index) = engine.indexes.index_mut::<C>() else { return };
+    if let Some(value) = value {
+        index.insert(entity, value);
+    }
+    let chained = index.chained.on_insert;
+    if let Some(hook) = chained {
+        hook(engine, entity, commands);
+    }
+}
+
+pub(crate) fn on_remove_index<C: Component + Clone>(engine: &mut Engine, entity: EntityId, commands: &mut Commands) {
+    let Some(index) = engine.indexes.index_mut::<C>() else { return };
+    index.remove(entity);
+    let chained = index.chained.on_remove;
+    if let Some(hook) = chained {
+        hook(engine, entity, commands);
+    }
+}
+
+/// Re-index the values of `C` that were edited in place since the last run.
+pub(crate) fn update_index<C: Component + Clone + PartialEq>(index: IndexMut<C>, ticks: Ticks, values: Query<(Ref<C>,)>) {
+    let Some(index) = index.engine.get_mut().indexes.index_mut::<C>() else { return };
+    let since = index.tick;
+    index.tick = ticks.advance();
+
+    for (value, entity) in values.iter().filter(|(value, _)| value.last_changed() > since) {
+        // values that were only borrowed mutably keep their place
+        if index.values.get(&entity) != Some(&*value) {
+            index.insert(entity, value.clone());
+        }
+    }
+}
+
+/// Read access to the index of `C` from a system, e.g. to find the entity with `NetId(42)`.
+pub struct Index<C: Component + Clone> {
+    engine: UnsafeRef<Engine>,
+    marker: PhantomData<C>,
+}
+
+impl<C: Component + Clone> Index<C> {
+    pub fn get(&self, value: &C) -> &[EntityId] {
+        self.engine.get().indexes.get::<C>(value)
+    }
+
+    /// Panics if `C` has a hash index rather than an ordered one.
+    pub fn range<R: RangeBounds<C>>(&self, range: R) -> impl Iterator<Item = EntityId> + '_ {
+        self.engine.get().indexes.range::<C, R>(range)
+    }
+
+    pub fn value(&self, entity: EntityId) -> Option<&C> {
+        self.engine.get().indexes.value::<C>(entity)
+    }
+}
+
+impl<C: Component + Clone> SystemParam for Index<C> {
+    fn fetch_param(engine: UnsafeRef<Engine>, _system: SystemIndex) -> Self {
+        Self { engine, marker: PhantomData }
+    }
+
+    fn fetch_access() -> Vec<Accessor> {
+        vec![Accessor::Ref(C::__internal_id())]
+    }
+
+    fn fetch_queries(_queries: &mut Vec<Vec<ComponentId>>) {
+        // do nothing
+    }
+}
+
+/// Write access to the index of `C`, for `update_index`.
+pub(crate) struct IndexMut<C: Component + Clone> {
+    engine: UnsafeRef<Engine>,
+    marker: PhantomData<C>,
+}
+
+impl<C: Component + Clone> SystemParam for IndexMut<C> {
+    fn fetch_param(engine: UnsafeRef<Engine>, _system: SystemIndex) -> Self {
+        Self { engine, marker: PhantomData }
+    }
+
+    fn fetch_access() -> Vec<Accessor> {
+        // readers of the index hold `Ref<C>`, so they never run alongside this
+        vec![Accessor::Mut(C::__internal_id())]
+    }
+
+    fn fetch_queries(_queries: &mut Vec<Vec<ComponentId>>) {
+        // do nothing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+    use crate::builder::EngineBuilder;
+    use crate::components::impl_component;
+
+    #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+    struct Level(u32);
+    impl_component!(Level);
+
+    static INSERTED: AtomicU32 = AtomicU32::new(0);
+
+    fn count_insert(_: &mut Engine, _: EntityId, _: &mut Commands) {
+        INSERTED.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn engine() -> Engine {
+        let mut builder = EngineBuilder::new();
+        builder.register_ordered_index::<Level>();
+        builder.build()
+    }
+
+    #[test]
+    fn get_and_range() {
+        let mut engine = engine();
+        let a = engine.spawn(|e| e.insert(Level(1)));
+        let b = engine.spawn(|e| e.insert(Level(5)));
+        let c = engine.spawn(|e| e.insert(Level(5)));
+
+        assert_eq!(engine.indexes.get(&Level(5)), &[b, c]);
+        assert!(engine.indexes.get(&Level(2)).is_empty());
+        assert_eq!(engine.indexes.range(Level(0)..Level(5)).collect::<Vec<_>>(), vec![a]);
+        assert_eq!(engine.indexes.range(Level(1)..).collect::<Vec<_>>(), vec![a, b, c]);
+        assert_eq!(engine.indexes.range(Level(5)..Level(1)).count(), 0);
+    }
+
+    #[test]
+    fn reinsert_and_remove() {
+        let mut engine = engine();
+        let a = engine.spawn(|e| e.insert(Level(1)));
+        let b = engine.spawn(|e| e.insert(Level(1)));
+
+        engine.insert(a, Level(2));
+        assert_eq!(engine.indexes.get(&Level(1)), &[b]);
+        assert_eq!(engine.indexes.get(&Level(2)), &[a]);
+        assert_eq!(engine.indexes.value::<Level>(a), Some(&Level(2)));
+
+        engine.remove::<Level>(a);
+        assert!(engine.indexes.get(&Level(2)).is_empty());
+        assert!(engine.indexes.value::<Level>(a).is_none());
+
+        engine.despawn(b);
+        assert!(engine.indexes.get(&Level(1)).is_empty());
+    }
+
+    #[test]
+    fn edits_in_place_are_reindexed() {
+        let mut engine = engine();
+        let a = engine.spawn(|e| e.insert(Level(1)));
+        let b = engine.spawn(|e| e.insert(Level(1)));
+
+        engine.get_mut::<Level>(a).unwrap().0 = 3;
+        engine.execute_systems();
+        assert_eq!(engine.indexes.get(&Level(1)), &[b]);
+        assert_eq!(engine.indexes.get(&Level(3)), &[a]);
+
+        // borrowing without changing the value keeps its place
+        engine.insert(a, Level(1));
+        engine.get_mut::<Level>(b).unwrap();
+        engine.execute_systems();
+        assert_eq!(engine.indexes.get(&Level(1)), &[b, a]);
+    }
+
+    #[test]
+    fn existing_hooks_still_run() {
+        let mut builder = EngineBuilder::new();
+        builder.on_insert::<Level>(count_insert).register_hash_index::<Level>();
+        let mut engine = builder.build();
+
+        let a = engine.spawn(|e| e.insert(Level(1)));
+        engine.insert(a, Level(2));
+        assert_eq!(INSERTED.load(Ordering::SeqCst), 2);
+        assert_eq!(engine.indexes.get(&Level(2)), &[a]);
+    }
+}
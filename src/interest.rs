@@ -0,0 +1,196 @@
+use std::collections::{BTreeMap, HashSet};
+
+use strata_traits::Component;
+
+use crate::changes::Tick;
+use crate::engine::Engine;
+use crate::entity::EntityId;
+use crate::replication::{PeerId, Replicated};
+use crate::spatial::SpatialIndex;
+use crate::transform::{GlobalTransform, Transform, Vec3};
+
+/// A component that places an entity in the world, for interest management.
+pub trait Position: Component {
+    fn position(&self) -> Vec3;
+}
+
+impl Position for Transform {
+    fn position(&self) -> Vec3 {
+        self.translation
+    }
+}
+
+impl Position for GlobalTransform {
+    fn position(&self) -> Vec3 {
+        self.translation()
+    }
+}
+
+/// Decides which `Replicated` entities each client is sent, by their position `P`. Pass it to
+/// `ReplicationServer::update_scoped`, which despawns entities that leave a client's scope on
+/// that client and sends entities that enter it in full. Replicated entities without `P` are
+/// in every scope. Clients without a rule see nothing else.
+///
+/// Positions are kept in a `SpatialIndex` that is only updated for entities whose `P` changed,
+/// so radius rules only look at nearby entities. Custom rules check every positioned entity.
+pub struct Interest<P: Position> {
+    rules: BTreeMap<PeerId, Rule<P>>,
+    scopes: BTreeMap<PeerId, Scope>,
+    /// replicated entities with `P`
+    index: SpatialIndex,
+    /// replicated entities without `P`, which every client sees
+    global: HashSet<EntityId>,
+    /// the tick of the last update
+    tick: Tick,
+}
+
+type CustomRule<P> = Box<dyn Fn(EntityId, &P) -> bool + Send + Sync>;
+
+enum Rule<P> {
+    Radius { viewer: EntityId, radius: f32 },
+    Custom(CustomRule<P>),
+}
+
+/// The positioned entities a client sees. Global entities are shared by every scope.
+#[derive(Default)]
+struct Scope {
+    visible: HashSet<EntityId>,
+    entered: Vec<EntityId>,
+    left: Vec<EntityId>,
+}
+
+impl<P: Position> Interest<P> {
+    /// Cells about the size of the usual radius work best, see `SpatialIndex`.
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            rules: BTreeMap::new(),
+            scopes: BTreeMap::new(),
+            index: SpatialIndex::new(cell_size),
+            global: HashSet::new(),
+            tick: 0,
+        }
+    }
+
+    /// Show `client` the entities within `radius` of `viewer`, e.g. its player.
+    pub fn set_radius(&mut self, client: PeerId, viewer: EntityId, radius: f32) {
+        self.rules.insert(client, Rule::Radius { viewer, radius });
+    }
+
+    /// Show `client` the entities for which `rule` returns true.
+    pub fn set_rule<F>(&mut self, client: PeerId, rule: F)
+    where
+        F: Fn(EntityId, &P) -> bool + Send + Sync + 'static
+    {
+        self.rules.insert(client, Rule::Custom(Box::new(rule)));
+    }
+
+    pub fn remove_client(&mut self, client: PeerId) {
+        self.rules.remove(&client);
+        self.scopes.remove(&client);
+    }
+
+    /// Move the entities whose position changed since the last update, then recompute the
+    /// scope of every client. Removals are only kept for two frames, so call this every frame.
+    pub fn update(&mut self, engine: &Engine) {
+        let (entered, left) = self.track(engine);
+
+        self.scopes.retain(|client, _| self.rules.contains_key(client));
+        for (client, rule) in self.rules.iter() {
+            let visible: HashSet<EntityId> = match rule {
+                Rule::Radius { viewer, radius } => match engine.get::<P>(*viewer) {
+                    Some(center) => self.index.query_radius(center.position(), *radius).into_iter().collect(),
+                    None => HashSet::new(),
+                },
+                Rule::Custom(rule) => self.index.entities()
+                    .filter(|id| engine.get::<P>(*id).is_some_and(|p| rule(*id, p)))
+                    .collect(),
+            };
+
+            // a new scope sees every global entity for the first time
+            let fresh = !self.scopes.contains_key(client);
+            let scope = self.scopes.entry(*client).or_default();
+            let was_global = |id: &EntityId| !fresh && (left.contains(id) || (self.global.contains(id) && !entered.contains(id)));
+
+            scope.entered = visible.iter()
+                .filter(|id| !scope.visible.contains(id) && !was_global(id))
+                .chain(match fresh {
+                    true => self.global.iter().collect::<Vec<_>>(),
+                    false => entered.iter().filter(|id| !scope.visible.contains(id)).collect(),
+                })
+                .copied()
+                .collect();
+            scope.left = scope.visible.iter()
+                .filter(|id| !visible.contains(id) && !self.global.contains(id))
+                .chain(left.iter().filter(|id| !fresh && !visible.contains(id)))
+                .copied()
+                .collect();
+            scope.entered.sort_unstable();
+            scope.left.sort_unstable();
+            scope.visible = visible;
+        }
+    }
+
+    /// Bring the index and the global entities up to date with what changed since the last
+    /// update. Returns the entities that became and stopped being global.
+    fn track(&mut self, engine: &Engine) -> (HashSet<EntityId>, HashSet<EntityId>) {
+        let since = self.tick;
+        self.tick = engine.advance_tick();
+
+        let mut entered = HashSet::new();
+        let mut left = HashSet::new();
+
+        // forget entities that lost `P` or `Replicated`, then add back the ones still replicated
+        let removed: HashSet<EntityId> = engine.despawned(since)
+            .chain(engine.removed::<P>(since))
+            .chain(engine.removed::<Replicated>(since))
+            .collect();
+        for entity in removed.iter() {
+            self.index.remove(*entity);
+            if self.global.remove(entity) {
+                left.insert(*entity);
+            }
+        }
+
+        for table in engine.archetypes.tables().iter().filter(|table| table.has(Replicated::__internal_id())) {
+            let replicated = table.ticks(Replicated::__internal_id()).unwrap();
+            if table.has(P::__internal_id()) {
+                let ticks = table.ticks(P::__internal_id()).unwrap();
+                for (col, (id, position)) in table.ids().iter().zip(table.collect::<P>().into_iter().flatten()).enumerate() {
+                    if ticks[col] > since || replicated[col] > since || removed.contains(id) {
+                        self.index.insert(*id, position.position());
+                        if self.global.remove(id) && !entered.remove(id) {
+                            left.insert(*id);
+                        }
+                    }
+                }
+            } else {
+                for (col, id) in table.ids().iter().enumerate() {
+                    if (replicated[col] > since || removed.contains(id)) && self.global.insert(*id) && !left.remove(id) {
+                        entered.insert(*id);
+                    }
+                }
+            }
+        }
+        (entered, left)
+    }
+
+    pub fn is_visible(&self, client: PeerId, entity: EntityId) -> bool {
+        self.scopes.get(&client).is_some_and(|scope| self.global.contains(&entity) || scope.visible.contains(&entity))
+    }
+
+    /// The entities `client` started seeing at the last `update`, in id order.
+    pub fn entered(&self, client: PeerId) -> &[EntityId] {
+        self.scopes.get(&client).map_or(&[], |scope| &scope.entered)
+    }
+
+    /// The entities `client` stopped seeing at the last `update`, in id order.
+    pub fn left(&self, client: PeerId) -> &[EntityId] {
+        self.scopes.get(&client).map_or(&[], |scope| &scope.left)
+    }
+
+    /// Every entity `client` sees.
+    pub fn scope(&self, client: PeerId) -> impl Iterator<Item = EntityId> + '_ {
+        self.scopes.get(&client).into_iter()
+            .flat_map(|scope| self.global.iter().chain(scope.visible.iter()).copied())
+    }
+}
@@ -1,6 +1,10 @@
 
+
 #![feature(downcast_unchecked)]
 
+// lets `#[derive(Reflect)]` name this crate as `::strata` from inside it too.
+extern crate self as strata;
+
 mod systems;
 mod scheduler;
 mod resources;
@@ -11,4 +15,26 @@ mod archetypes;
 mod anon;
 mod table;
 mod query;
-mod builder;
\ No newline at end of file
+mod builder;
+mod bundle;
+mod components;
+mod hooks;
+mod observers;
+mod hierarchy;
+mod relations;
+mod transform;
+mod registry;
+mod snapshot;
+mod scene;
+mod reflect;
+mod clone;
+mod rollback;
+mod replay;
+mod checksum;
+mod replication;
+mod interest;
+mod spatial;
+mod index;
+mod changes;
+
+pub use reflect::Reflect;
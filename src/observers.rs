@@ -0,0 +1,184 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::commands::Commands;
+use crate::components::impl_component;
+use crate::engine::Engine;
+use crate::entity::EntityId;
+
+/// Something that can be triggered with `Commands::trigger`.
+pub trait Event: Send + Sync + 'static {
+    /// Whether targeted triggers bubble to `traverse` unless an observer stops them.
+    const AUTO_PROPAGATE: bool = false;
+
+    /// The entity a targeted trigger bubbles to next. Return `hierarchy::parent_of`
+    /// to bubble up to parent entities.
+    fn traverse(engine: &Engine, entity: EntityId) -> Option<EntityId> {
+        let _ = (engine, entity);
+        None
+    }
+}
+
+/// The event an observer is running for, and the entity it was triggered on.
+pub struct Trigger<'a, E: Event> {
+    event: &'a E,
+    target: Option<EntityId>,
+    observer: EntityId,
+    propagate: bool,
+}
+
+impl<'a, E: Event> Trigger<'a, E> {
+    pub fn event(&self) -> &E {
+        self.event
+    }
+
+    /// The entity the event was triggered on, or `None` for a global trigger.
+    pub fn target(&self) -> Option<EntityId> {
+        self.target
+    }
+
+    /// The entity the running observer is stored on.
+    pub fn observer(&self) -> EntityId {
+        self.observer
+    }
+
+    /// Set whether this trigger bubbles to the next entity given by `Event::traverse`.
+    pub fn propagate(&mut self, propagate: bool) {
+        self.propagate = propagate;
+    }
+}
+
+type ObserverRunner = Arc<dyn Fn(&mut Engine, &dyn Any, Option<EntityId>, EntityId, &mut bool) + Send + Sync>;
+
+/// A component that makes its entity run a function whenever an `E` is triggered.
+/// Despawning the entity removes the observer.
+#[derive(Clone)]
+pub struct Observer {
+    event: TypeId,
+    targets: Vec<EntityId>,
+    runner: ObserverRunner,
+}
+
+impl_component!(Observer);
+
+impl Observer {
+    /// Observers are plain functions rather than systems, since they run in the middle
+    /// of a flush. They get the whole engine instead of system params.
+    pub fn new<E, F>(observer: F) -> Self
+    where
+        E: Event,
+        F: Fn(&mut Trigger<E>, &mut Engine) + Send + Sync + 'static,
+    {
+        let runner = move |engine: &mut Engine, event: &dyn Any, target: Option<EntityId>, entity: EntityId, propagate: &mut bool| {
+            if let Some(event) = event.downcast_ref::<E>() {
+                let mut trigger = Trigger { event, target, observer: entity, propagate: *propagate };
+                observer(&mut trigger, engine);
+                *propagate = trigger.propagate;
+            }
+        };
+
+        Self {
+            event: TypeId::of::<E>(),
+            targets: Vec::new(),
+            runner: Arc::new(runner),
+        }
+    }
+
+    /// Only run for triggers targeting `entity`. An observer
+    /// that watches nothing runs for every trigger of its event.
+    pub fn watch(mut self, entity: EntityId) -> Self {
+        self.targets.push(entity);
+        self
+    }
+}
+
+/// Every observer entity, indexed by the event it observes.
+/// Kept in sync by the `Observer` component hooks.
+#[derive(Clone)]
+pub struct Observers {
+    observers: HashMap<TypeId, Vec<ObserverEntry>>,
+}
+
+#[derive(Clone)]
+struct ObserverEntry {
+    entity: EntityId,
+    targets: Vec<EntityId>,
+    runner: ObserverRunner,
+}
+
+impl Observers {
+    pub fn new() -> Self {
+        Self {
+            observers: HashMap::new(),
+        }
+    }
+
+    fn insert(&mut self, entity: EntityId, observer: &Observer) {
+        self.remove(entity);
+        self.observers.entry(observer.event).or_default().push(ObserverEntry {
+            entity,
+            targets: observer.targets.clone(),
+            runner: observer.runner.clone(),
+        });
+    }
+
+    fn remove(&mut self, entity: EntityId) {
+        for entries in self.observers.values_mut() {
+            entries.retain(|entry| entry.entity != entity);
+        }
+    }
+
+    /// Observers of `event` that don't watch specific entities.
+    fn global(&self, event: TypeId) -> Vec<(EntityId, ObserverRunner)> {
+        match self.observers.get(&event) {
+            Some(entries) => entries.iter()
+                .filter(|entry| entry.targets.is_empty())
+                .map(|entry| (entry.entity, entry.runner.clone()))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Observers of `event` watching `target`.
+    fn watching(&self, event: TypeId, target: EntityId) -> Vec<(EntityId, ObserverRunner)> {
+        match self.observers.get(&event) {
+            Some(entries) => entries.iter()
+                .filter(|entry| entry.targets.contains(&target))
+                .map(|entry| (entry.entity, entry.runner.clone()))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Run every observer of `E`. Global observers run once, then observers watching
+/// the target run, bubbling along `Event::traverse` while the trigger propagates.
+pub(crate) fn trigger<E: Event>(engine: &mut Engine, event: E, target: Option<EntityId>) {
+    let id = TypeId::of::<E>();
+    let mut propagate = E::AUTO_PROPAGATE;
+
+    for (observer, runner) in engine.observers.global(id) {
+        let mut ignored = propagate;
+        runner(engine, &event, target, observer, &mut ignored);
+    }
+
+    let mut current = target;
+    while let Some(entity) = current {
+        for (observer, runner) in engine.observers.watching(id, entity) {
+            runner(engine, &event, Some(entity), observer, &mut propagate);
+        }
+
+        current = if propagate { E::traverse(engine, entity) } else { None };
+    }
+}
+
+pub(crate) fn on_insert_observer(engine: &mut Engine, entity: EntityId, _: &mut Commands) {
+    if let Some(observer) = engine.archetypes.get::<Observer>(entity) {
+        engine.observers.insert(entity, observer);
+    }
+}
+
+pub(crate) fn on_remove_observer(engine: &mut Engine, entity: EntityId, _: &mut Commands) {
+    engine.observers.remove(entity);
+}
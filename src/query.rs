@@ -8,7 +8,7 @@ use strata_traits::Component;
 
 use crate::anon::AnonIterChain;
 use crate::engine::Engine;
-use crate::entity::{EntityIndex, EntityIndexChain};
+use crate::entity::{EntityId, EntityIndex};
 use crate::systems::SystemParam;
 use crate::archetypes::ComponentId;
 use crate::scheduler::Accessor;
@@ -17,15 +17,219 @@ use crate::archetypes::Archetype;
 use crate::archetypes::Archetypes;
 use crate::resources::Resources;
 use crate::scheduler::UnsafeRef;
+use crate::scheduler::SystemIndex;
+use crate::relations::Relation;
+use crate::changes::{ChangeTick, Tick};
 
 pub struct Query<Q: IntoQuery> {
     engine: UnsafeRef<Engine>,
     marker: PhantomData<Q>,
 }
 
+impl<Q: IntoQuery> Query<Q> {
+    /// Iterate without consuming the query.
+    pub fn iter(&self) -> Q::Item {
+        Q::into_query(self.engine.clone())
+    }
+
+    /// The components of a single entity, if it matches this query.
+    pub fn get(&self, id: EntityId) -> Option<<Q::Item as Iterator>::Item> {
+        Q::get(self.engine.clone(), id)
+    }
+
+    /// Every item whose entity has a `Rel<R>` pointing at `target`, e.g. every `ChildOf(ship)`.
+    /// To match any target, i.e. `R(*)`, query `Ref<Rel<R>>` instead.
+    pub fn iter_related<R: Relation>(&self, target: EntityId) -> impl Iterator<Item = <Q::Item as Iterator>::Item> + '_ {
+        self.engine.get().relations.sources::<R>(target).iter().filter_map(move |source| self.get(*source))
+    }
+
+    /// Iterate in entity id order, which doesn't change as entities move between
+    /// or within tables. Only the ids are sorted, not the components.
+    pub fn iter_by_entity_id(&self) -> Sorted<Q> {
+        let mut order = self.locations();
+        order.sort_unstable_by_key(|(id, _)| *id);
+        self.sorted(order)
+    }
+
+    /// Iterate ordered by `key`, with ties in entity id order. Each item is fetched once
+    /// to compute its key and again when it is yielded, so only the keys are stored.
+    pub fn iter_sorted_by_key<K, F>(&self, mut key: F) -> Sorted<Q>
+    where
+        K: Ord,
+        F: FnMut(&<Q::Item as Iterator>::Item) -> K,
+    {
+        let mut keyed: Vec<(K, EntityId, EntityIndex)> = self.locations().into_iter()
+            .filter_map(|(id, index)| Some((key(&Q::fetch(self.engine.clone(), index, id)?), id, index)))
+            .collect();
+        keyed.sort_unstable_by(|a, b| a.0.cmp(&b.0).then(a.1.cmp(&b.1)));
+        self.sorted(keyed.into_iter().map(|(_, id, index)| (id, index)).collect())
+    }
+
+    /// Pair every item with the item of `other` for the entity `key` returns, e.g. each
+    /// weapon with its owner's stats. Items whose key is `None` or whose entity doesn't
+    /// match `other` are skipped. Several items can share a key, so `other` must not write 
+    /// any component. Panics if it does, or if this query writes a component `other` reads.
+    pub fn join<'a, B, F>(&self, other: &'a Query<B>, key: F) -> Joined<'a, Q, B, F>
+    where
+        B: IntoQuery,
+        F: FnMut(&<Q::Item as Iterator>::Item) -> Option<EntityId>,
+    {
+        check_join(&Q::accessors(), &B::accessors());
+
+        Joined {
+            left: self.iter(),
+            right: other,
+            key,
+        }
+    }
+
+    /// Where every matching entity is stored, in table order.
+    fn locations(&self) -> Vec<(EntityId, EntityIndex)> {
+        let archetypes = &self.engine.get().archetypes;
+        let mut out = Vec::new();
+        for table in archetypes.query(&Q::archetype()).iter() {
+            let ids = archetypes.tables()[*table].ids();
+            out.extend(ids.iter().enumerate().map(|(col, id)| (*id, EntityIndex { table: *table, col })));
+        }
+        out
+    }
+
+    fn sorted(&self, order: Vec<(EntityId, EntityIndex)>) -> Sorted<Q> {
+        Sorted {
+            engine: self.engine.clone(),
+            order: order.into_iter(),
+            marker: PhantomData,
+        }
+    }
+}
+
+/// Pairs of items from `Query::join` or `Join::iter`.
+pub struct Joined<'a, A: IntoQuery, B: IntoQuery, F> {
+    left: A::Item,
+    right: &'a Query<B>,
+    key: F,
+}
+
+impl<'a, A, B, F> Iterator for Joined<'a, A, B, F>
+where
+    A: IntoQuery,
+    B: IntoQuery,
+    F: FnMut(&<A::Item as Iterator>::Item) -> Option<EntityId>,
+{
+    type Item = (<A::Item as Iterator>::Item, <B::Item as Iterator>::Item);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let item = self.left.next()?;
+            if let Some(other) = (self.key)(&item).and_then(|target| self.right.get(target)) {
+                return Some((item, other));
+            }
+        }
+    }
+}
+
+/// Two queries joined on an entity, as one system parameter. The scheduler sees the access
+/// of both, and a system whose queries would alias, or whose `B` writes a component, is
+/// rejected when it is loaded.
+pub struct Join<A: IntoQuery, B: IntoQuery> {
+    left: Query<A>,
+    right: Query<B>,
+}
+
+impl<A: IntoQuery, B: IntoQuery> Join<A, B> {
+    /// Pair every item of `A` with the item of `B` for the entity `key` returns.
+    pub fn iter<F>(&self, key: F) -> Joined<'_, A, B, F>
+    where
+        F: FnMut(&<A::Item as Iterator>::Item) -> Option<EntityId>,
+    {
+        self.left.join(&self.right, key)
+    }
+
+    pub fn left(&self) -> &Query<A> {
+        &self.left
+    }
+
+    pub fn right(&self) -> &Query<B> {
+        &self.right
+    }
+}
+
+impl<A: IntoQuery, B: IntoQuery> SystemParam for Join<A, B> {
+    fn fetch_param(engine: UnsafeRef<Engine>, system: SystemIndex) -> Self {
+        Self {
+            left: Query::fetch_param(engine.clone(), system),
+            right: Query::fetch_param(engine, system),
+        }
+    }
+
+    fn fetch_access() -> Vec<Accessor> {
+        let (mut left, mut right) = (A::accessors(), B::accessors());
+        check_join(&left, &right);
+
+        left.append(&mut right);
+        left.sort();
+        left.dedup();
+        left
+    }
+
+    fn fetch_queries(queries: &mut Vec<Vec<ComponentId>>) {
+        A::queries(queries);
+        B::queries(queries);
+    }
+}
+
+/// Panic if a join of these queries could hand out aliased components. An item on the right
+/// is fetched once for every item on the left with its key, so it has to be read only.
+fn check_join(left: &[Accessor], right: &[Accessor]) {
+    if let Some(id) = conflict(left, right) {
+        panic!("Attempted to join queries that both access component {:#x}, one of them mutably!", id);
+    }
+    if let Some(id) = right.iter().find_map(|accessor| match accessor { Accessor::Mut(id) => Some(*id), _ => None }) {
+        panic!("Attempted to join a query that writes component {:#x} on the right!", id);
+    }
+}
+
+/// A component one side writes and the other reads or writes.
+fn conflict(left: &[Accessor], right: &[Accessor]) -> Option<ComponentId> {
+    let writes = |access: &[Accessor], id: ComponentId| access.contains(&Accessor::Mut(id));
+    let touches = |access: &[Accessor], id: ComponentId| writes(access, id) || access.contains(&Accessor::Ref(id));
+
+    left.iter().chain(right.iter()).find_map(|accessor| match *accessor {
+        Accessor::Ref(id) | Accessor::Mut(id) => {
+            let aliased = (writes(left, id) && touches(right, id)) || (writes(right, id) && touches(left, id));
+            aliased.then_some(id)
+        }
+        _ => None,
+    })
+}
+
+/// The items of a query in a fixed order, from `Query::iter_by_entity_id`
+/// or `Query::iter_sorted_by_key`.
+pub struct Sorted<Q: IntoQuery> {
+    engine: UnsafeRef<Engine>,
+    order: std::vec::IntoIter<(EntityId, EntityIndex)>,
+    marker: PhantomData<fn() -> Q>,
+}
+
+impl<Q: IntoQuery> Iterator for Sorted<Q> {
+    type Item = <Q::Item as Iterator>::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (id, index) = self.order.next()?;
+        Q::fetch(self.engine.clone(), index, id)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.order.size_hint()
+    }
+}
+
+unsafe impl<Q: IntoQuery> Send for Query<Q> {}
+unsafe impl<Q: IntoQuery> Sync for Query<Q> {}
+
 // Make Query a System Parameter
 impl<Q: IntoQuery> SystemParam for Query<Q> {
-    fn fetch_param(engine: UnsafeRef<Engine>) -> Self {
+    fn fetch_param(engine: UnsafeRef<Engine>, system: SystemIndex) -> Self {
         Self { engine: engine, marker: PhantomData }
     }
 
@@ -54,6 +258,11 @@ pub trait IntoQuery: 'static {
     type Item: Iterator;
 
     fn into_query(engine: UnsafeRef<Engine>) -> Self::Item;
+    fn get(engine: UnsafeRef<Engine>, id: EntityId) -> Option<<Self::Item as Iterator>::Item>;
+    /// The components of the entity `id`, stored at `index`.
+    fn fetch(engine: UnsafeRef<Engine>, index: EntityIndex, id: EntityId) -> Option<<Self::Item as Iterator>::Item>;
+    /// The components every matching table has.
+    fn archetype() -> Archetype;
     fn accessors() -> Vec<Accessor>;
     fn queries(queries: &mut Vec<Vec<ComponentId>>);
 }
@@ -63,11 +272,53 @@ pub trait QueryParam: 'static {
 
     fn collect(engine: &Arc<Engine>, ids: &IndexSet<TableIndex>) -> AnonIterChain<Self::Item>;
     fn as_accessor() -> Accessor;
-    fn wrap(data: &'static mut Self::Item) -> Self;
+    /// Wrap a component and its change tick. `now` is the engine's tick, for stamping writes.
+    fn wrap(data: &'static mut Self::Item, tick: &'static mut Tick, now: &'static ChangeTick) -> Self;
+}
+
+/// A column of components and their change ticks, iterated together.
+pub struct Fetch<C: Component> {
+    values: AnonIterChain<C>,
+    ticks: AnonIterChain<Tick>,
+}
+
+impl<C: Component> Fetch<C> {
+    fn new(archetypes: &Archetypes, indices: &IndexSet<TableIndex>) -> Self {
+        Self {
+            values: archetypes.collect::<C>(indices),
+            ticks: archetypes.collect_ticks::<C>(indices),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+impl<C: Component> Iterator for Fetch<C> {
+    type Item = (&'static mut C, &'static mut Tick);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some((self.values.next()?, self.ticks.next()?))
+    }
+}
+
+/// The engine's tick, for the items of a query. The engine outlives 
+/// every query, the same as the components they hand out.
+fn now(engine: &UnsafeRef<Engine>) -> &'static ChangeTick {
+    unsafe { &*(engine.get().archetypes.tick() as *const ChangeTick) }
 }
 
 pub struct Ref<C: Component> {
     inner: &'static C,
+    tick: Tick,
+}
+
+impl<C: Component> Ref<C> {
+    /// The tick this component was last inserted or changed at.
+    pub fn last_changed(&self) -> Tick {
+        self.tick
+    }
 }
 
 impl<C: Component> Deref for Ref<C> {
@@ -89,15 +340,26 @@ impl<C: Component> QueryParam for Ref<C> {
         Accessor::Ref(C::__internal_id())
     }
 
-    fn wrap(data: &'static mut Self::Item) -> Self {
+    fn wrap(data: &'static mut Self::Item, tick: &'static mut Tick, _now: &'static ChangeTick) -> Self {
         Self {
-            inner: data
+            inner: data,
+            tick: *tick,
         }
     }
 }
 
+/// A mutable component. Borrowing it mutably marks it as changed.
 pub struct Mut<C: Component> {
     inner: &'static mut C,
+    tick: &'static mut Tick,
+    now: &'static ChangeTick,
+}
+
+impl<C: Component> Mut<C> {
+    /// The tick this component was last inserted or changed at.
+    pub fn last_changed(&self) -> Tick {
+        *self.tick
+    }
 }
 
 impl<C: Component> Deref for Mut<C> {
@@ -110,6 +372,7 @@ impl<C: Component> Deref for Mut<C> {
 
 impl<C: Component> DerefMut for Mut<C> {
     fn deref_mut(&mut self) -> &mut Self::Target {
+        *self.tick = self.now.get();
         self.inner
     }
 }
@@ -125,9 +388,11 @@ impl<C: Component> QueryParam for Mut<C> {
         Accessor::Mut(C::__internal_id())
     }
 
-    fn wrap(data: &'static mut Self::Item) -> Self {
+    fn wrap(data: &'static mut Self::Item, tick: &'static mut Tick, now: &'static ChangeTick) -> Self {
         Self {
             inner: data,
+            tick,
+            now,
         }
     }
 }
@@ -136,21 +401,23 @@ pub struct Query1<Q1>
 where
     Q1: QueryParam
 {
-    q1: AnonIterChain<Q1::Item>,
-    e: EntityIndexChain,
+    q1: Fetch<Q1::Item>,
+    e: AnonIterChain<EntityId>,
+    now: &'static ChangeTick,
 }
 
 impl<Q1> Iterator for Query1<Q1>
 where
     Q1: QueryParam
 {
-    type Item = (Q1, EntityIndex);
+    type Item = (Q1, EntityId);
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.q1.iters.len() > 0 {
+        if !self.q1.is_empty() {
+            let (data, tick) = self.q1.next().unwrap();
             Some((
-                Q1::wrap(self.q1.next().unwrap()), 
-                self.e.next().unwrap()
+                Q1::wrap(data, tick, self.now), 
+                *self.e.next().unwrap()
             ))
         } else {
             None
@@ -165,17 +432,31 @@ where
     type Item = Query1<Q1>;
 
     fn into_query(engine: UnsafeRef<Engine>) -> Self::Item {
-        let mut archetype = Archetype::new();
-        archetype.add(Q1::Item::__internal_id());
-
-        let indices = engine.get().archetypes.query(archetype);
+        let indices = engine.get().archetypes.query(&Self::archetype());
 
         Query1 {
-            q1: engine.get().archetypes.collect::<Q1::Item>(indices),
-            e: engine.get().archetypes.collect_indices(indices),
+            q1: Fetch::new(&engine.get().archetypes, indices),
+            e: engine.get().archetypes.collect_ids(indices),
+            now: now(&engine),
         }
     }
 
+    fn get(engine: UnsafeRef<Engine>, id: EntityId) -> Option<<Self::Item as Iterator>::Item> {
+        let index = engine.get().archetypes.locate(id)?;
+        Self::fetch(engine, index, id)
+    }
+
+    fn fetch(engine: UnsafeRef<Engine>, index: EntityIndex, id: EntityId) -> Option<<Self::Item as Iterator>::Item> {
+        let (data, tick) = engine.get().archetypes.fetch::<Q1::Item>(index)?;
+        Some((Q1::wrap(data, tick, now(&engine)), id))
+    }
+
+    fn archetype() -> Archetype {
+        let mut archetype = Archetype::new();
+        archetype.add(Q1::Item::__internal_id());
+        archetype
+    }
+
     fn accessors() -> Vec<Accessor> {
         vec![Q1::as_accessor()]
     }
@@ -202,21 +483,25 @@ pub mod macros {
             where
                 $($t2: QueryParam),*
             {
-                $($t3: AnonIterChain<$t2::Item>),*,
-                e: EntityIndexChain,
+                $($t3: Fetch<$t2::Item>),*,
+                e: AnonIterChain<EntityId>,
+                now: &'static ChangeTick,
             }
 
             impl<$($t2),*> Iterator for $t1<$($t2),*>
             where
                 $($t2: QueryParam),*
             {
-                type Item = ($($t2),*, EntityIndex);
+                type Item = ($($t2),*, EntityId);
 
                 fn next(&mut self) -> Option<Self::Item> {
-                    if self.t1.iters.len() > 0 {
+                    if !self.t1.is_empty() {
                         Some((
-                            $($t2::wrap(self.$t3.next().unwrap())),*, 
-                            self.e.next().unwrap()
+                            $({
+                                let (data, tick) = self.$t3.next().unwrap();
+                                $t2::wrap(data, tick, self.now)
+                            }),*, 
+                            *self.e.next().unwrap()
                         ))
                     } else {
                         None
@@ -231,16 +516,35 @@ pub mod macros {
                 type Item = $t1<$($t2),*>;
 
                 fn into_query(engine: UnsafeRef<Engine>) -> Self::Item {
-                    let mut archetype = Archetype::new();
-                    $(archetype.add($t2::Item::__internal_id());)*
-                    let indices = engine.get().archetypes.query(archetype);
+                    let indices = engine.get().archetypes.query(&Self::archetype());
 
                     $t1 {
-                        $($t3: engine.get().archetypes.collect::<$t2::Item>(indices)),*,
-                        e: engine.get().archetypes.collect_indices(indices)
+                        $($t3: Fetch::new(&engine.get().archetypes, indices)),*,
+                        e: engine.get().archetypes.collect_ids(indices),
+                        now: now(&engine),
                     }
                 }
 
+                fn get(engine: UnsafeRef<Engine>, id: EntityId) -> Option<<Self::Item as Iterator>::Item> {
+                    let index = engine.get().archetypes.locate(id)?;
+                    Self::fetch(engine, index, id)
+                }
+
+                fn fetch(engine: UnsafeRef<Engine>, index: EntityIndex, id: EntityId) -> Option<<Self::Item as Iterator>::Item> {
+                    let archetypes = &engine.get().archetypes;
+                    let now = now(&engine);
+                    Some(($({
+                        let (data, tick) = archetypes.fetch::<$t2::Item>(index)?;
+                        $t2::wrap(data, tick, now)
+                    }),*, id))
+                }
+
+                fn archetype() -> Archetype {
+                    let mut archetype = Archetype::new();
+                    $(archetype.add($t2::Item::__internal_id());)*
+                    archetype
+                }
+
                 fn accessors() -> Vec<Accessor> {
                     vec![$($t2::as_accessor()),*]
                 }
@@ -253,4 +557,82 @@ pub mod macros {
     }
 
     pub(crate) use impl_query;
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::EngineBuilder;
+    use crate::components::impl_component;
+
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    struct Score(u32);
+    impl_component!(Score);
+
+    /// A query outside of a system, registered the way loading a system would.
+    fn query<Q: IntoQuery>(engine: &mut Engine) -> Query<Q> {
+        let mut queries = Vec::new();
+        Q::queries(&mut queries);
+        queries.into_iter().for_each(|ids| engine.archetypes.add_query(ids));
+        Query { engine: UnsafeRef::new(engine), marker: PhantomData }
+    }
+
+    #[test]
+    fn sorted_iteration_survives_swap_removal() {
+        let mut engine = EngineBuilder::new().build();
+        let entities: Vec<EntityId> = [3, 1, 3, 2, 1].into_iter()
+            .map(|score| engine.spawn(|e| e.insert(Score(score))))
+            .collect();
+
+        // the last entity is swapped into the despawned one's column
+        engine.despawn(entities[1]);
+        let scores = query::<(Ref<Score>,)>(&mut engine);
+        let table_order: Vec<EntityId> = scores.iter().map(|(_, id)| id).collect();
+        assert_eq!(table_order, vec![entities[0], entities[4], entities[2], entities[3]]);
+
+        let by_id: Vec<EntityId> = scores.iter_by_entity_id().map(|(_, id)| id).collect();
+        assert_eq!(by_id, vec![entities[0], entities[2], entities[3], entities[4]]);
+
+        // ties are in entity id order
+        let by_score: Vec<EntityId> = scores.iter_sorted_by_key(|(score, _)| score.0).map(|(_, id)| id).collect();
+        assert_eq!(by_score, vec![entities[4], entities[3], entities[0], entities[2]]);
+    }
+
+    struct Owner(Option<EntityId>);
+    impl_component!(Owner);
+
+    #[test]
+    fn join_pairs_items_with_their_keys() {
+        let mut engine = EngineBuilder::new().build();
+        let player = engine.spawn(|e| e.insert(Score(7)));
+        let unscored = engine.spawn(|_| {});
+        let sword = engine.spawn(|e| e.insert(Owner(Some(player))));
+        let shield = engine.spawn(|e| e.insert(Owner(Some(player))));
+        engine.spawn(|e| e.insert(Owner(Some(unscored))));
+        engine.spawn(|e| e.insert(Owner(None)));
+
+        let owners = query::<(Ref<Owner>,)>(&mut engine);
+        let scores = query::<(Ref<Score>,)>(&mut engine);
+        let joined: Vec<(EntityId, EntityId, u32)> = owners.join(&scores, |(owner, _)| owner.0)
+            .map(|((_, item), (score, owner))| (item, owner, score.0))
+            .collect();
+        assert_eq!(joined, vec![(sword, player, 7), (shield, player, 7)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "on the right")]
+    fn join_rejects_writes_on_the_right() {
+        let mut engine = EngineBuilder::new().build();
+        let owners = query::<(Ref<Owner>,)>(&mut engine);
+        let scores = query::<(Mut<Score>,)>(&mut engine);
+        owners.join(&scores, |(owner, _)| owner.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "one of them mutably")]
+    fn join_rejects_aliased_components() {
+        let mut engine = EngineBuilder::new().build();
+        let scores = query::<(Mut<Score>,)>(&mut engine);
+        let others = query::<(Ref<Score>,)>(&mut engine);
+        scores.join(&others, |_| None);
+    }
+}
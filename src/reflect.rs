@@ -0,0 +1,145 @@
+use std::any::{type_name, Any};
+
+use crate::entity::EntityId;
+
+pub use strata_derive::Reflect;
+
+/// Runtime access to the fields of a type, so tools can inspect and edit components
+/// and resources without knowing their types. Implement it with `#[derive(Reflect)]`.
+pub trait Reflect: Send + Sync + 'static {
+    fn type_name(&self) -> &'static str;
+
+    /// The names of the fields, in declaration order. Empty for plain values.
+    fn field_names(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    fn field(&self, name: &str) -> Option<&dyn Reflect> {
+        let _ = name;
+        None
+    }
+
+    fn field_mut(&mut self, name: &str) -> Option<&mut dyn Reflect> {
+        let _ = name;
+        None
+    }
+
+    /// Set a plain value from text, e.g. from a debug console. Returns false if
+    /// the text doesn't parse or this type has fields.
+    fn set_str(&mut self, text: &str) -> bool {
+        let _ = text;
+        false
+    }
+
+    /// A readable representation, e.g. `Vec3 { x: 1, y: 0, z: 0 }`.
+    fn describe(&self) -> String;
+
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl dyn Reflect {
+    /// The value at a `.` separated path of field names, e.g. `translation.x`.
+    /// An empty path is the value itself.
+    pub fn path(&self, path: &str) -> Option<&dyn Reflect> {
+        if path.is_empty() {
+            return Some(self);
+        }
+        path.split('.').try_fold(self, |value, name| value.field(name))
+    }
+
+    pub fn path_mut(&mut self, path: &str) -> Option<&mut dyn Reflect> {
+        if path.is_empty() {
+            return Some(self);
+        }
+        path.split('.').try_fold(self, |value, name| value.field_mut(name))
+    }
+
+    pub fn get<T: 'static>(&self, path: &str) -> Option<&T> {
+        self.path(path)?.as_any().downcast_ref::<T>()
+    }
+
+    /// Set the value at `path`. Returns false if there is no such field or it is not a `T`.
+    pub fn set<T: 'static>(&mut self, path: &str, value: T) -> bool {
+        match self.path_mut(path).and_then(|field| field.as_any_mut().downcast_mut::<T>()) {
+            Some(field) => {
+                *field = value;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+macros::impl_reflect_value!(bool, char, String);
+macros::impl_reflect_value!(u8, u16, u32, u64, u128, usize);
+macros::impl_reflect_value!(i8, i16, i32, i64, i128, isize);
+macros::impl_reflect_value!(f32, f64);
+
+/// Entity ids are edited as their `to_bits` value.
+impl Reflect for EntityId {
+    fn type_name(&self) -> &'static str {
+        type_name::<Self>()
+    }
+
+    fn set_str(&mut self, text: &str) -> bool {
+        match text.parse::<u64>() {
+            Ok(bits) => {
+                *self = EntityId::from_bits(bits);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!("{}v{}", self.index(), self.generation())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+pub mod macros {
+    /// Implement `Reflect` for plain values that parse from and display as text.
+    macro_rules! impl_reflect_value {
+        ($($t:ty),*) => {
+            $(
+                impl Reflect for $t {
+                    fn type_name(&self) -> &'static str {
+                        type_name::<Self>()
+                    }
+
+                    fn set_str(&mut self, text: &str) -> bool {
+                        match text.parse::<$t>() {
+                            Ok(value) => {
+                                *self = value;
+                                true
+                            }
+                            Err(_) => false,
+                        }
+                    }
+
+                    fn describe(&self) -> String {
+                        self.to_string()
+                    }
+
+                    fn as_any(&self) -> &dyn Any {
+                        self
+                    }
+
+                    fn as_any_mut(&mut self) -> &mut dyn Any {
+                        self
+                    }
+                }
+            )*
+        };
+    }
+
+    pub(crate) use impl_reflect_value;
+}
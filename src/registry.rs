@@ -0,0 +1,312 @@
+use std::any::{type_name, Any};
+use std::collections::HashMap;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use strata_traits::{Component, Resource};
+
+use crate::anon::{Anon, AnonVec};
+use crate::archetypes::ComponentId;
+use crate::checksum::{StableHash, StableHasher};
+use crate::components::short_name;
+use crate::entity::EntityId;
+use crate::reflect::Reflect;
+use crate::resources::{ResourceId, Resources};
+
+/// A component holding entity ids that must be rewritten when it is loaded
+/// into another engine, e.g. `Parent` or `Rel<R>`.
+pub trait MapEntities {
+    fn map_entities(&mut self, map: &EntityMap);
+}
+
+/// The ids entities had when they were saved, mapped to the ids they were given when loaded.
+#[derive(Default)]
+pub struct EntityMap {
+    map: HashMap<EntityId, EntityId>,
+}
+
+impl EntityMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, old: EntityId, new: EntityId) {
+        self.map.insert(old, new);
+    }
+
+    pub fn get(&self, old: EntityId) -> Option<EntityId> {
+        self.map.get(&old).copied()
+    }
+
+    pub fn remove(&mut self, old: EntityId) -> Option<EntityId> {
+        self.map.remove(&old)
+    }
+
+    /// The new id of `old`. Ids of entities that were not loaded map to
+    /// `EntityId::DANGLING`, so they never refer to an unrelated entity.
+    pub fn map(&self, old: EntityId) -> EntityId {
+        self.get(old).unwrap_or(EntityId::DANGLING)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (EntityId, EntityId)> + '_ {
+        self.map.iter().map(|(old, new)| (*old, *new))
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+/// Functions for the components and resources that opted in to serialization.
+#[derive(Clone)]
+pub struct TypeRegistry {
+    components: HashMap<ComponentId, ComponentFns>,
+    resources: HashMap<ResourceId, ResourceFns>,
+    maps: HashMap<ComponentId, fn(&Anon, &EntityMap)>,
+    reflect: HashMap<ComponentId, ReflectFns>,
+    reflect_resources: HashMap<ResourceId, (&'static str, ReflectResourceFns)>,
+    rollback: HashMap<ResourceId, RollbackFns>,
+    hash: HashMap<ComponentId, HashFn>,
+    /// sorted, so replicated components are sent in a stable order
+    replicated: Vec<ComponentId>,
+}
+
+/// View the component at an index of a column as `dyn Reflect`.
+#[derive(Copy, Clone)]
+pub struct ReflectFns {
+    pub get: fn(&AnonVec, usize) -> &dyn Reflect,
+    pub get_mut: fn(&mut AnonVec, usize) -> &mut dyn Reflect,
+}
+
+#[derive(Copy, Clone)]
+pub struct ReflectResourceFns {
+    pub get: fn(&Resources) -> &dyn Reflect,
+    pub get_mut: fn(&mut Resources) -> &mut dyn Reflect,
+}
+/// Hash the component at an index of a column.
+pub type HashFn = fn(&AnonVec, usize, &mut StableHasher);
+
+#[derive(Copy, Clone)]
+pub struct ComponentFns {
+    pub serialize: fn(&AnonVec, usize) -> bincode::Result<Vec<u8>>,
+    /// Serialize a component that is not stored in a table yet, e.g. one in a queue.
+    pub serialize_anon: fn(&Anon) -> bincode::Result<Vec<u8>>,
+    pub deserialize: fn(&[u8]) -> bincode::Result<Anon>,
+    /// Read a component from a value in a scene file.
+    pub from_value: fn(ron::Value) -> Result<Anon, ron::Error>,
+}
+
+#[derive(Copy, Clone)]
+pub struct ResourceFns {
+    pub serialize: fn(&Resources) -> bincode::Result<Vec<u8>>,
+    pub deserialize: fn(&mut Resources, &[u8]) -> bincode::Result<()>,
+}
+
+/// Copies a resource for `Engine::fork` and `Engine::save_state`.
+#[derive(Copy, Clone)]
+pub struct RollbackFns {
+    /// Copy the resource into a saved slot, reusing the value already there.
+    pub save: fn(&Resources, &mut Option<Box<dyn Any + Send + Sync>>),
+    pub load: fn(&mut Resources, &(dyn Any + Send + Sync)),
+    pub fork: fn(&Resources, &mut Resources),
+}
+
+impl TypeRegistry {
+    pub fn new() -> Self {
+        Self {
+            components: HashMap::new(),
+            resources: HashMap::new(),
+            maps: HashMap::new(),
+            reflect: HashMap::new(),
+            reflect_resources: HashMap::new(),
+            rollback: HashMap::new(),
+            hash: HashMap::new(),
+            replicated: Vec::new(),
+        }
+    }
+
+    pub fn register_component<C>(&mut self)
+    where
+        C: Component + Serialize + DeserializeOwned
+    {
+        self.components.insert(C::__internal_id(), ComponentFns {
+            serialize: serialize_component::<C>,
+            serialize_anon: serialize_anon::<C>,
+            deserialize: deserialize_component::<C>,
+            from_value: component_from_value::<C>,
+        });
+    }
+
+    pub fn register_resource<R>(&mut self)
+    where
+        R: Resource + Serialize + DeserializeOwned + Send + Sync
+    {
+        self.resources.insert(R::__internal_id(), ResourceFns {
+            serialize: serialize_resource::<R>,
+            deserialize: deserialize_resource::<R>,
+        });
+    }
+
+    pub fn register_rollback<R: Resource + Clone + Send + Sync>(&mut self) {
+        self.rollback.insert(R::__internal_id(), RollbackFns {
+            save: save_rollback::<R>,
+            load: load_rollback::<R>,
+            fork: fork_rollback::<R>,
+        });
+    }
+
+    /// Every rollback resource, sorted by id.
+    pub fn rollback(&self) -> Vec<(ResourceId, RollbackFns)> {
+        let mut out: Vec<_> = self.rollback.iter().map(|(id, fns)| (*id, *fns)).collect();
+        out.sort_by_key(|(id, _)| *id);
+        out
+    }
+
+    /// Mark a serializable component to be sent by a `ReplicationServer`.
+    pub fn register_replicated(&mut self, id: ComponentId) {
+        if let Err(i) = self.replicated.binary_search(&id) {
+            self.replicated.insert(i, id);
+        }
+    }
+
+    pub fn replicated(&self) -> &[ComponentId] {
+        &self.replicated
+    }
+
+    pub fn is_replicated(&self, id: ComponentId) -> bool {
+        self.replicated.binary_search(&id).is_ok()
+    }
+
+    pub fn register_hash<C: Component + StableHash>(&mut self) {
+        self.hash.insert(C::__internal_id(), hash_component::<C>);
+    }
+
+    pub fn hash(&self, id: ComponentId) -> Option<HashFn> {
+        self.hash.get(&id).copied()
+    }
+
+    pub fn register_map_entities<C: Component + MapEntities>(&mut self) {
+        self.maps.insert(C::__internal_id(), map_component::<C>);
+    }
+
+    pub fn register_reflect<C: Component + Reflect>(&mut self) {
+        self.reflect.insert(C::__internal_id(), ReflectFns {
+            get: reflect_component::<C>,
+            get_mut: reflect_component_mut::<C>,
+        });
+    }
+
+    pub fn register_reflect_resource<R: Resource + Reflect>(&mut self) {
+        self.reflect_resources.insert(R::__internal_id(), (type_name::<R>(), ReflectResourceFns {
+            get: reflect_resource::<R>,
+            get_mut: reflect_resource_mut::<R>,
+        }));
+    }
+
+    pub fn reflect(&self, id: ComponentId) -> Option<ReflectFns> {
+        self.reflect.get(&id).copied()
+    }
+
+    /// Find a reflected resource by its short or full type name.
+    pub fn reflect_resource(&self, name: &str) -> Option<(ResourceId, ReflectResourceFns)> {
+        self.reflect_resources.iter()
+            .find(|(_, (full, _))| *full == name || short_name(full) == name)
+            .map(|(id, (_, f))| (*id, *f))
+    }
+
+    pub fn component(&self, id: ComponentId) -> Option<&ComponentFns> {
+        self.components.get(&id)
+    }
+
+    pub fn resource(&self, id: ResourceId) -> Option<&ResourceFns> {
+        self.resources.get(&id)
+    }
+
+    /// Every registered resource, sorted by id so output is stable.
+    pub fn resources(&self) -> Vec<(ResourceId, ResourceFns)> {
+        let mut out: Vec<_> = self.resources.iter().map(|(id, fns)| (*id, *fns)).collect();
+        out.sort_by_key(|(id, _)| *id);
+        out
+    }
+
+    /// Rewrite the entity ids stored in `anon`, if its component has any.
+    pub fn map_entities(&self, anon: &Anon, map: &EntityMap) {
+        if let Some(f) = self.maps.get(&anon.id()) {
+            f(anon, map);
+        }
+    }
+}
+
+fn serialize_component<C: Component + Serialize>(column: &AnonVec, index: usize) -> bincode::Result<Vec<u8>> {
+    bincode::serialize(column.get_as::<C>(index))
+}
+
+fn serialize_anon<C: Component + Serialize>(anon: &Anon) -> bincode::Result<Vec<u8>> {
+    bincode::serialize(anon.downcast::<C>())
+}
+
+fn deserialize_component<C: Component + DeserializeOwned>(bytes: &[u8]) -> bincode::Result<Anon> {
+    Ok(Anon::new::<C>(bincode::deserialize(bytes)?))
+}
+
+fn component_from_value<C: Component + DeserializeOwned>(value: ron::Value) -> Result<Anon, ron::Error> {
+    Ok(Anon::new::<C>(value.into_rust()?))
+}
+
+fn serialize_resource<R: Resource + Serialize>(resources: &Resources) -> bincode::Result<Vec<u8>> {
+    bincode::serialize(unsafe { &*resources.get::<R>() })
+}
+
+fn deserialize_resource<R>(resources: &mut Resources, bytes: &[u8]) -> bincode::Result<()>
+where
+    R: Resource + DeserializeOwned + Send + Sync
+{
+    resources.insert::<R>(bincode::deserialize(bytes)?);
+    Ok(())
+}
+
+fn reflect_component<C: Component + Reflect>(column: &AnonVec, index: usize) -> &dyn Reflect {
+    column.get_as::<C>(index)
+}
+
+fn reflect_component_mut<C: Component + Reflect>(column: &mut AnonVec, index: usize) -> &mut dyn Reflect {
+    column.get_mut_as::<C>(index)
+}
+
+fn reflect_resource<R: Resource + Reflect>(resources: &Resources) -> &dyn Reflect {
+    unsafe { &*resources.get::<R>() }
+}
+
+fn reflect_resource_mut<R: Resource + Reflect>(resources: &mut Resources) -> &mut dyn Reflect {
+    unsafe { resources.get::<R>() }
+}
+
+fn save_rollback<R: Resource + Clone + Send + Sync>(resources: &Resources, slot: &mut Option<Box<dyn Any + Send + Sync>>) {
+    let resource = unsafe { &*resources.get::<R>() };
+    match slot.as_mut().and_then(|saved| saved.downcast_mut::<R>()) {
+        Some(saved) => saved.clone_from(resource),
+        None => *slot = Some(Box::new(resource.clone())),
+    }
+}
+
+fn load_rollback<R: Resource + Clone + Send + Sync>(resources: &mut Resources, saved: &(dyn Any + Send + Sync)) {
+    if let Some(saved) = saved.downcast_ref::<R>() {
+        unsafe { resources.get::<R>() }.clone_from(saved);
+    }
+}
+
+fn fork_rollback<R: Resource + Clone + Send + Sync>(resources: &Resources, out: &mut Resources) {
+    out.insert::<R>(unsafe { resources.get::<R>() }.clone());
+}
+
+fn hash_component<C: Component + StableHash>(column: &AnonVec, index: usize, hasher: &mut StableHasher) {
+    column.get_as::<C>(index).stable_hash(hasher);
+}
+
+fn map_component<C: Component + MapEntities>(anon: &Anon, map: &EntityMap) {
+    anon.downcast_mut::<C>().map_entities(map);
+}
@@ -0,0 +1,304 @@
+use std::any::type_name;
+use std::collections::{BTreeMap, HashMap};
+use std::marker::PhantomData;
+
+use serde::{Deserialize, Serialize};
+use strata_traits::Component;
+
+use crate::archetypes::ComponentId;
+use crate::commands::{Commands, EntityCommands};
+use crate::components::id_of;
+use crate::engine::Engine;
+use crate::entity::EntityId;
+use crate::registry::{EntityMap, MapEntities};
+use crate::scheduler::{Accessor, SystemIndex, UnsafeRef};
+use crate::systems::SystemParam;
+
+/// A kind of relationship between two entities, e.g. `Likes` or `Owns`.
+pub trait Relation: Send + Sync + 'static {
+    /// What happens to the entities relating to a target when the target is destroyed.
+    const CLEANUP: Cleanup = Cleanup::Remove;
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Cleanup {
+    /// Remove the `Rel<R>` from every entity that pointed at the target.
+    Remove,
+    /// Destroy every entity that pointed at the target.
+    Despawn,
+}
+
+/// Relationships of kind `R` from the entity holding it to one or more targets. Use
+/// `EntityCommands::relate` and `unrelate` to add and remove targets one at a time.
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct Rel<R: Relation> {
+    targets: Vec<EntityId>,
+    marker: PhantomData<R>,
+}
+
+impl<R: Relation> Rel<R> {
+    pub fn new(target: EntityId) -> Self {
+        Self {
+            targets: vec![target],
+            marker: PhantomData,
+        }
+    }
+
+    /// The targets in the order they were related.
+    pub fn targets(&self) -> &[EntityId] {
+        &self.targets
+    }
+
+    pub fn contains(&self, target: EntityId) -> bool {
+        self.targets.contains(&target)
+    }
+}
+
+impl<R: Relation> Clone for Rel<R> {
+    fn clone(&self) -> Self {
+        Self {
+            targets: self.targets.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<R: Relation> MapEntities for Rel<R> {
+    fn map_entities(&mut self, map: &EntityMap) {
+        for target in self.targets.iter_mut() {
+            *target = map.map(*target);
+        }
+    }
+}
+
+impl<R: Relation> Component for Rel<R> {
+    fn __internal_id() -> u64 {
+        id_of(type_name::<Self>())
+    }
+}
+
+/// Drop one target from a source's `Rel<R>`, removing it once it has none.
+type DetachFn = fn(&mut Engine, EntityId, EntityId, &mut Commands);
+
+/// Reverse lookups for every registered relation, kept in sync by the `Rel<R>` hooks.
+#[derive(Clone)]
+pub struct Relations {
+    kinds: BTreeMap<ComponentId, RelationIndex>,
+}
+
+#[derive(Clone)]
+struct RelationIndex {
+    cleanup: Cleanup,
+    detach: DetachFn,
+    /// target -> every entity relating to it
+    sources: HashMap<EntityId, Vec<EntityId>>,
+    /// source -> its targets
+    targets: BTreeMap<EntityId, Vec<EntityId>>,
+}
+
+impl Relations {
+    pub fn new() -> Self {
+        Self {
+            kinds: BTreeMap::new(),
+        }
+    }
+
+    pub(crate) fn register<R: Relation>(&mut self) {
+        self.kinds.entry(Rel::<R>::__internal_id()).or_insert(RelationIndex {
+            cleanup: R::CLEANUP,
+            detach: detach::<R>,
+            sources: HashMap::new(),
+            targets: BTreeMap::new(),
+        });
+    }
+
+    /// Every entity with a `Rel<R>` pointing at `target`, i.e. `R(target)`.
+    pub fn sources<R: Relation>(&self, target: EntityId) -> &[EntityId] {
+        self.kinds.get(&Rel::<R>::__internal_id())
+            .and_then(|index| index.sources.get(&target))
+            .map(|sources| sources.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// The targets of `source`'s `Rel<R>`.
+    pub fn targets<R: Relation>(&self, source: EntityId) -> &[EntityId] {
+        self.kinds.get(&Rel::<R>::__internal_id())
+            .and_then(|index| index.targets.get(&source))
+            .map(|targets| targets.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Every `(source, target)` pair of kind `R`, i.e. `R(*)`, ordered by source.
+    pub fn iter<R: Relation>(&self) -> impl Iterator<Item = (EntityId, EntityId)> + '_ {
+        self.kinds.get(&Rel::<R>::__internal_id())
+            .into_iter()
+            .flat_map(|index| index.targets.iter())
+            .flat_map(|(s, targets)| targets.iter().map(move |t| (*s, *t)))
+    }
+
+    /// Make `targets` the only targets of `source`.
+    fn link(&mut self, id: ComponentId, source: EntityId, targets: &[EntityId]) {
+        self.unlink(id, source);
+        if let Some(index) = self.kinds.get_mut(&id) {
+            if targets.is_empty() {
+                return;
+            }
+            for target in targets {
+                index.sources.entry(*target).or_default().push(source);
+            }
+            index.targets.insert(source, targets.to_vec());
+        }
+    }
+
+    fn unlink(&mut self, id: ComponentId, source: EntityId) {
+        if let Some(index) = self.kinds.get_mut(&id) {
+            for old in index.targets.remove(&source).unwrap_or_default() {
+                if let Some(sources) = index.sources.get_mut(&old) {
+                    sources.retain(|s| *s != source);
+                    if sources.is_empty() {
+                        index.sources.remove(&old);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'a> EntityCommands<'a> {
+    /// Add `target` to this entity's `Rel<R>`, inserting it if the entity has none.
+    pub fn relate<R: Relation>(&mut self, target: EntityId) -> &mut Self {
+        let source = self.id();
+        self.commands().defer(move |engine| relate::<R>(engine, source, target));
+        self
+    }
+
+    /// Remove `target` from this entity's `Rel<R>`, removing it once it has no targets left.
+    pub fn unrelate<R: Relation>(&mut self, target: EntityId) -> &mut Self {
+        let source = self.id();
+        self.commands().defer(move |engine| {
+            let mut commands = engine.commands();
+            detach::<R>(engine, source, target, &mut commands);
+        });
+        self
+    }
+}
+
+// like the hierarchy, these run as deferred work inside a flush, so they change a `Rel<R>`
+// in place, or one queued earlier in the same flush, and only queue new inserts.
+
+fn relate<R: Relation>(engine: &mut Engine, source: EntityId, target: EntityId) {
+    if !engine.contains(source) || !engine.contains(target) {
+        return;
+    }
+
+    // a queued insert is linked by its hook when it is applied
+    if let Some(rel) = engine.archetypes.queued_mut::<Rel<R>>(source) {
+        if !rel.contains(target) {
+            rel.targets.push(target);
+        }
+        return;
+    }
+
+    match engine.archetypes.get_mut::<Rel<R>>(source) {
+        Some(rel) => {
+            if !rel.contains(target) {
+                rel.targets.push(target);
+            }
+            let targets = rel.targets.clone();
+            engine.relations.link(Rel::<R>::__internal_id(), source, &targets);
+        }
+        None => engine.commands().insert(source, Rel::<R>::new(target)),
+    }
+}
+
+fn detach<R: Relation>(engine: &mut Engine, source: EntityId, target: EntityId, commands: &mut Commands) {
+    if let Some(rel) = engine.archetypes.queued_mut::<Rel<R>>(source) {
+        rel.targets.retain(|t| *t != target);
+    }
+
+    if let Some(rel) = engine.archetypes.get_mut::<Rel<R>>(source) {
+        rel.targets.retain(|t| *t != target);
+        if rel.targets.is_empty() {
+            commands.remove::<Rel<R>>(source);
+        } else {
+            let targets = rel.targets.clone();
+            engine.relations.link(Rel::<R>::__internal_id(), source, &targets);
+        }
+    }
+}
+
+/// Apply the cleanup policy of every relation pointing at an entity about to be destroyed,
+/// in the same order on every run.
+pub(crate) fn cleanup(engine: &mut Engine, despawned: &[EntityId]) -> Commands {
+    let mut work = Vec::new();
+    for index in engine.relations.kinds.values_mut() {
+        for target in despawned {
+            for source in index.sources.remove(target).unwrap_or_default() {
+                if let Some(targets) = index.targets.get_mut(&source) {
+                    targets.retain(|t| t != target);
+                    if targets.is_empty() {
+                        index.targets.remove(&source);
+                    }
+                }
+                work.push((index.cleanup, index.detach, source, *target));
+            }
+        }
+    }
+
+    let mut commands = engine.commands();
+    for (cleanup, detach, source, target) in work {
+        match cleanup {
+            Cleanup::Remove => detach(engine, source, target, &mut commands),
+            Cleanup::Despawn => commands.destroy(source),
+        }
+    }
+    commands
+}
+
+pub(crate) fn on_insert_rel<R: Relation>(engine: &mut Engine, entity: EntityId, _: &mut Commands) {
+    if let Some(rel) = engine.archetypes.get::<Rel<R>>(entity) {
+        let targets = rel.targets.clone();
+        engine.relations.link(Rel::<R>::__internal_id(), entity, &targets);
+    }
+}
+
+pub(crate) fn on_remove_rel<R: Relation>(engine: &mut Engine, entity: EntityId, _: &mut Commands) {
+    engine.relations.unlink(Rel::<R>::__internal_id(), entity);
+}
+
+/// Read access to the relations of kind `R` from a system.
+pub struct Related<R: Relation> {
+    engine: UnsafeRef<Engine>,
+    marker: PhantomData<R>,
+}
+
+impl<R: Relation> Related<R> {
+    /// Every entity related to `target`, i.e. `R(target)`.
+    pub fn sources(&self, target: EntityId) -> &[EntityId] {
+        self.engine.get().relations.sources::<R>(target)
+    }
+
+    pub fn targets(&self, source: EntityId) -> &[EntityId] {
+        self.engine.get().relations.targets::<R>(source)
+    }
+
+    /// Every `(source, target)` pair, i.e. `R(*)`.
+    pub fn iter(&self) -> impl Iterator<Item = (EntityId, EntityId)> + '_ {
+        self.engine.get().relations.iter::<R>()
+    }
+}
+
+impl<R: Relation> SystemParam for Related<R> {
+    fn fetch_param(engine: UnsafeRef<Engine>, system: SystemIndex) -> Self {
+        Self { engine, marker: PhantomData }
+    }
+
+    fn fetch_access() -> Vec<Accessor> {
+        vec![Accessor::Ref(Rel::<R>::__internal_id())]
+    }
+
+    fn fetch_queries(queries: &mut Vec<Vec<ComponentId>>) {
+        // do nothing
+    }
+}
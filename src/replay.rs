@@ -0,0 +1,393 @@
+use std::any::type_name;
+use std::fmt;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use strata_traits::Resource;
+
+use crate::anon::Anon;
+use crate::archetypes::ComponentId;
+use crate::checksum::WorldChecksum;
+use crate::commands::{Commands, Queue};
+use crate::engine::Engine;
+use crate::entity::{Entity, EntityId};
+use crate::resources::ResourceId;
+
+const MAGIC: [u8; 4] = *b"STRR";
+
+/// Bumped whenever the layout of `Recording` changes.
+pub const RECORDING_VERSION: u32 = 3;
+
+#[derive(Debug)]
+pub enum ReplayError {
+    /// The data does not start with the recording magic bytes.
+    NotARecording,
+    UnsupportedVersion(u32),
+    /// The engine was not built with `EngineBuilder::strict`, so spawned ids can't be replayed.
+    NotStrict,
+    /// A component was not registered with `EngineBuilder::register_serializable`.
+    NotSerializable(String),
+    /// A resource was not registered with `EngineBuilder::register_serializable_resource`.
+    NotSerializableResource(String),
+    /// A component in the recording is not registered as serializable in this engine.
+    UnknownComponent(ComponentId),
+    /// A resource in the recording is not registered as serializable in this engine.
+    UnknownResource(ResourceId),
+    /// Commands holding closures, e.g. from `Commands::add` or `Commands::trigger`, can't be recorded.
+    Deferred,
+    /// A recorded spawn was given another id than when it was recorded.
+    EntityMismatch { frame: usize, expected: EntityId, found: EntityId },
+    /// An id recorded from `Commands::get_or_spawn` was alive or could not be claimed.
+    Unclaimable { frame: usize, entity: EntityId },
+    /// The world differs from the recording after this frame. Lists the hashed components
+    /// that differ, which is empty if only the entities that exist differ.
+    Diverged { frame: usize, components: Vec<String> },
+    Bincode(bincode::Error),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::NotARecording => write!(f, "not a strata recording"),
+            ReplayError::UnsupportedVersion(v) => write!(f, "unsupported recording version {} (expected {})", v, RECORDING_VERSION),
+            ReplayError::NotStrict => write!(f, "recording and replaying require a strict engine"),
+            ReplayError::NotSerializable(name) => write!(f, "component {} is not registered as serializable", name),
+            ReplayError::NotSerializableResource(name) => write!(f, "resource {} is not registered as serializable", name),
+            ReplayError::UnknownComponent(id) => write!(f, "component {:#x} is not registered as serializable", id),
+            ReplayError::UnknownResource(id) => write!(f, "resource {:#x} is not registered as serializable", id),
+            ReplayError::Deferred => write!(f, "commands with closures can't be recorded"),
+            ReplayError::EntityMismatch { frame, expected, found } => write!(f, "frame {} spawned {:?} instead of {:?}", frame, found, expected),
+            ReplayError::Unclaimable { frame, entity } => write!(f, "frame {} could not claim {:?}", frame, entity),
+            ReplayError::Diverged { frame, components } if components.is_empty() => write!(f, "diverged at frame {}: entities differ", frame),
+            ReplayError::Diverged { frame, components } => write!(f, "diverged at frame {}: {} differ", frame, components.join(", ")),
+            ReplayError::Bincode(e) => write!(f, "{}", e),
+            ReplayError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<bincode::Error> for ReplayError {
+    fn from(e: bincode::Error) -> Self {
+        ReplayError::Bincode(e)
+    }
+}
+
+impl From<std::io::Error> for ReplayError {
+    fn from(e: std::io::Error) -> Self {
+        ReplayError::Io(e)
+    }
+}
+
+/// The inputs and commands of every frame of a run, with a checksum of the world after each.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Recording {
+    frames: Vec<Frame>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Frame {
+    inputs: Vec<(ResourceId, Vec<u8>)>,
+    commands: Vec<RecordedQueue>,
+    checksum: WorldChecksum,
+}
+
+type RecordedComponents = Vec<(ComponentId, Vec<u8>)>;
+
+/// A `Queue` with its components serialized.
+#[derive(Default, Serialize, Deserialize)]
+struct RecordedQueue {
+    spawn: Vec<(EntityId, RecordedComponents)>,
+    /// Spawns whose ids came from `Commands::get_or_spawn`.
+    claimed: Vec<EntityId>,
+    destroy: Vec<EntityId>,
+    modify: Vec<(EntityId, RecordedComponents, Vec<ComponentId>)>,
+}
+
+/// Records a run so it can be replayed with `Recording::replay`. The engine must be built with
+/// `EngineBuilder::strict`. Start recording right after `Engine::execute_startup`, then for
+/// every frame pass inputs to `input`, commands to `commands` and run the systems with `execute`:
+///
+/// ```ignore
+/// let mut recorder = Recorder::new();
+/// loop {
+///     recorder.input(&mut engine, poll_input())?;
+///     recorder.commands(&mut engine, |commands| { commands.spawn(|e| e.insert(Bullet)); })?;
+///     recorder.execute(&mut engine)?;
+/// }
+/// recorder.finish().save("bug.strr")?;
+/// ```
+///
+/// Commands queued by systems, hooks and observers are not recorded, since replaying
+/// the systems queues them again. Each frame is checked with `Engine::checksum`, so only
+/// components registered with `EngineBuilder::register_hash` are compared.
+pub struct Recorder {
+    recording: Recording,
+    frame: Frame,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self {
+            recording: Recording::default(),
+            frame: Frame::default(),
+        }
+    }
+
+    /// Insert `input` as a resource and record it for this frame.
+    pub fn input<R>(&mut self, engine: &mut Engine, input: R) -> Result<(), ReplayError>
+    where
+        R: Resource + Serialize + Send + Sync
+    {
+        check_strict(engine)?;
+        if engine.registry.resource(R::__internal_id()).is_none() {
+            return Err(ReplayError::NotSerializableResource(type_name::<R>().to_string()));
+        }
+
+        self.frame.inputs.push((R::__internal_id(), bincode::serialize(&input)?));
+        engine.resources.insert(input);
+        Ok(())
+    }
+
+    /// Queue and record the commands written by `f`. Nothing is queued if they can't be
+    /// recorded, i.e. if they hold closures or components that are not serializable.
+    pub fn commands<F>(&mut self, engine: &mut Engine, f: F) -> Result<(), ReplayError>
+    where
+        F: FnOnce(&mut Commands)
+    {
+        check_strict(engine)?;
+        let mut commands = engine.commands();
+        f(&mut commands);
+        let queue = commands.take_queue();
+        drop(commands);
+
+        match record(engine, &queue) {
+            Ok(recorded) => {
+                self.frame.commands.push(recorded);
+                engine.archetypes.queue(queue);
+                Ok(())
+            }
+            Err(e) => {
+                discard(engine, queue);
+                Err(e)
+            }
+        }
+    }
+
+    /// Run the systems once and record a checksum of the world, ending the frame.
+    pub fn execute(&mut self, engine: &mut Engine) -> Result<(), ReplayError> {
+        check_strict(engine)?;
+        engine.execute_systems();
+        self.frame.checksum = engine.checksum();
+        self.recording.frames.push(std::mem::take(&mut self.frame));
+        Ok(())
+    }
+
+    /// The frames recorded so far. Inputs and commands of an unfinished frame are left out.
+    pub fn finish(self) -> Recording {
+        self.recording
+    }
+}
+
+impl Recording {
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ReplayError> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&MAGIC);
+        out.extend_from_slice(&RECORDING_VERSION.to_le_bytes());
+        bincode::serialize_into(&mut out, self)?;
+        Ok(out)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ReplayError> {
+        if bytes.len() < 8 || bytes[..4] != MAGIC {
+            return Err(ReplayError::NotARecording);
+        }
+        let version = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        if version != RECORDING_VERSION {
+            return Err(ReplayError::UnsupportedVersion(version));
+        }
+        Ok(bincode::deserialize(&bytes[8..])?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ReplayError> {
+        Ok(std::fs::write(path, self.to_bytes()?)?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ReplayError> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// Feed every frame into `engine`, which must be built by the same builder and
+    /// have run `execute_startup`. Stops at the first frame whose checksum differs,
+    /// naming the components that differ.
+    pub fn replay(&self, engine: &mut Engine) -> Result<(), ReplayError> {
+        for frame in 0..self.frames.len() {
+            self.replay_frame(engine, frame)?;
+        }
+        Ok(())
+    }
+
+    /// Feed a single frame into `engine`, e.g. to step through a recording.
+    pub fn replay_frame(&self, engine: &mut Engine, frame: usize) -> Result<(), ReplayError> {
+        check_strict(engine)?;
+        let recorded = &self.frames[frame];
+
+        for (id, data) in recorded.inputs.iter() {
+            let fns = engine.registry.resource(*id).ok_or(ReplayError::UnknownResource(*id))?;
+            (fns.deserialize)(&mut engine.resources, data)?;
+        }
+
+        for commands in recorded.commands.iter() {
+            let queue = rebuild(engine, commands, frame)?;
+            engine.archetypes.queue(queue);
+        }
+
+        engine.execute_systems();
+        let found = engine.checksum();
+        if found.total != recorded.checksum.total {
+            return Err(ReplayError::Diverged { frame, components: recorded.checksum.diff(&found) });
+        }
+        Ok(())
+    }
+}
+
+/// Ids are only reserved in the same order every run in strict mode.
+fn check_strict(engine: &Engine) -> Result<(), ReplayError> {
+    if engine.systems.is_strict() { Ok(()) } else { Err(ReplayError::NotStrict) }
+}
+
+fn record(engine: &Engine, queue: &Queue) -> Result<RecordedQueue, ReplayError> {
+    if queue.deferred.as_ref().is_some_and(|deferred| !deferred.is_empty()) {
+        return Err(ReplayError::Deferred);
+    }
+
+    let mut out = RecordedQueue::default();
+    for entity in queue.spawn.iter().flat_map(|spawn| spawn.values()) {
+        out.spawn.push((entity.id(), record_components(engine, &entity.components)?));
+    }
+    out.claimed.extend(queue.claimed.iter().flatten()
+        .filter(|id| out.spawn.iter().any(|(spawned, _)| spawned == *id)));
+    out.destroy.extend(queue.destroy.iter().flatten());
+    for (id, (insert, remove)) in queue.modify.iter().flatten() {
+        out.modify.push((*id, record_components(engine, insert)?, remove.clone()));
+    }
+    Ok(out)
+}
+
+fn record_components(engine: &Engine, anons: &[Anon]) -> Result<RecordedComponents, ReplayError> {
+    let mut out = Vec::with_capacity(anons.len());
+    for anon in anons {
+        let fns = engine.registry.component(anon.id())
+            .ok_or_else(|| ReplayError::NotSerializable(anon.name().to_string()))?;
+        out.push((anon.id(), (fns.serialize_anon)(anon)?));
+    }
+    Ok(out)
+}
+
+/// Drop a queue without applying it, freeing the ids it reserved.
+fn discard(engine: &Engine, queue: Queue) {
+    for entity in queue.spawn.iter().flat_map(|spawn| spawn.values()) {
+        engine.archetypes.release(entity.id());
+    }
+    clear(queue);
+}
+
+fn clear(queue: Queue) {
+    for entity in queue.spawn.into_iter().flat_map(|spawn| spawn.into_values()) {
+        entity.components.iter().for_each(|anon| anon.clear());
+    }
+    for (_, (insert, _)) in queue.modify.into_iter().flatten() {
+        insert.iter().for_each(|anon| anon.clear());
+    }
+}
+
+fn rebuild(engine: &Engine, recorded: &RecordedQueue, frame: usize) -> Result<Queue, ReplayError> {
+    let mut queue = Queue::default();
+
+    for (id, components) in recorded.spawn.iter() {
+        match build_components(engine, components) {
+            Ok(anons) => {
+                let mut entity = Entity::new(*id);
+                anons.into_iter().for_each(|anon| entity.insert_anon(anon));
+                queue.spawn(entity);
+            }
+            Err(e) => {
+                clear(queue);
+                return Err(e);
+            }
+        }
+    }
+
+    for (id, insert, remove) in recorded.modify.iter() {
+        match build_components(engine, insert) {
+            Ok(anons) => {
+                anons.into_iter().for_each(|anon| queue.insert(anon, *id));
+                remove.iter().for_each(|cmp| queue.remove(*id, *cmp));
+            }
+            Err(e) => {
+                clear(queue);
+                return Err(e);
+            }
+        }
+    }
+
+    // ids are reserved and claimed in the order they were when recorded, so
+    // they match as long as the world hasn't diverged
+    let ids: Vec<EntityId> = queue.spawn.iter().flat_map(|spawn| spawn.keys().copied()).collect();
+    for (i, expected) in ids.iter().enumerate() {
+        let error = if recorded.claimed.contains(expected) {
+            match engine.archetypes.claim(*expected) {
+                Ok(true) => None,
+                _ => Some(ReplayError::Unclaimable { frame, entity: *expected }),
+            }
+        } else {
+            let found = engine.archetypes.reserve();
+            if found == *expected {
+                None
+            } else {
+                engine.archetypes.release(found);
+                Some(ReplayError::EntityMismatch { frame, expected: *expected, found })
+            }
+        };
+
+        if let Some(e) = error {
+            // released in reverse so the free list ends up as it was
+            ids[..i].iter().rev().for_each(|id| engine.archetypes.release(*id));
+            clear(queue);
+            return Err(e);
+        }
+    }
+
+    for id in recorded.destroy.iter() {
+        queue.destroy(*id);
+    }
+    Ok(queue)
+}
+
+fn build_components(engine: &Engine, recorded: &RecordedComponents) -> Result<Vec<Anon>, ReplayError> {
+    let mut out = Vec::with_capacity(recorded.len());
+    for (id, data) in recorded.iter() {
+        let built = engine.registry.component(*id)
+            .ok_or(ReplayError::UnknownComponent(*id))
+            .and_then(|fns| Ok((fns.deserialize)(data)?));
+
+        match built {
+            Ok(anon) => out.push(anon),
+            Err(e) => {
+                out.iter().for_each(|anon| anon.clear());
+                return Err(e);
+            }
+        }
+    }
+    Ok(out)
+}
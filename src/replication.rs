@@ -0,0 +1,817 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use strata_traits::Component;
+
+use crate::anon::Anon;
+use crate::archetypes::ComponentId;
+use crate::builder::{EngineBuilder, Plugin};
+use crate::changes::Tick;
+use crate::components::impl_component;
+use crate::engine::Engine;
+use crate::entity::{Entity, EntityId};
+use crate::hierarchy::{Children, Parent};
+use crate::interest::{Interest, Position};
+use crate::registry::EntityMap;
+
+/// Identifies the other end of a `Transport`.
+pub type PeerId = u32;
+
+/// Moves packets between a server and its clients. Packets may be dropped, duplicated or
+/// reordered, since the server keeps sending changes until a client acknowledges them.
+pub trait Transport {
+    fn send(&mut self, peer: PeerId, packet: &[u8]) -> io::Result<()>;
+
+    /// The next packet that arrived, if any. Must not block.
+    fn receive(&mut self) -> io::Result<Option<(PeerId, Vec<u8>)>>;
+}
+
+/// Marks an entity to be mirrored to clients by a `ReplicationServer`. Only its components
+/// registered with `EngineBuilder::register_replicated` are sent. Clients add it too.
+#[derive(Clone, Copy)]
+pub struct Replicated;
+
+impl_component!(Replicated);
+
+/// Replicates the hierarchy along with every `Replicated` entity.
+pub struct ReplicationPlugin;
+
+impl Plugin for ReplicationPlugin {
+    fn build(&self, builder: &mut EngineBuilder) {
+        builder
+            .register_component::<Replicated>()
+            .register_clone::<Replicated>()
+            .register_replicated::<Parent>()
+            .register_replicated::<Children>();
+    }
+}
+
+#[derive(Debug)]
+pub enum ReplicationError {
+    /// A component in a packet is not registered as replicated on this engine.
+    UnknownComponent(ComponentId),
+    Bincode(bincode::Error),
+    Io(io::Error),
+}
+
+impl fmt::Display for ReplicationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplicationError::UnknownComponent(id) => write!(f, "component {:#x} is not registered as replicated", id),
+            ReplicationError::Bincode(e) => write!(f, "{}", e),
+            ReplicationError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ReplicationError {}
+
+impl From<bincode::Error> for ReplicationError {
+    fn from(e: bincode::Error) -> Self {
+        ReplicationError::Bincode(e)
+    }
+}
+
+impl From<io::Error> for ReplicationError {
+    fn from(e: io::Error) -> Self {
+        ReplicationError::Io(e)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+enum Message {
+    /// Sent by a client to start receiving updates.
+    Hello,
+    /// Sent by a client once it received every packet of the update at this tick.
+    Ack(Tick),
+    Update(Update),
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Update {
+    tick: Tick,
+    /// which of the `parts` packets of this update this is
+    part: u32,
+    parts: u32,
+    entities: Vec<EntityUpdate>,
+    despawned: Vec<EntityId>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EntityUpdate {
+    id: EntityId,
+    changed: Vec<(ComponentId, Vec<u8>)>,
+    removed: Vec<ComponentId>,
+}
+
+/// Sends what changed on `Replicated` entities to every client. Every update holds everything
+/// that changed since the last update a client acknowledged, so lost packets are made up for
+/// by the next update.
+pub struct ReplicationServer<T: Transport> {
+    transport: T,
+    clients: BTreeMap<PeerId, ClientState>,
+    tick: Tick,
+    max_packet: usize,
+}
+
+/// What a client has been sent, and what it acknowledged receiving.
+#[derive(Default)]
+struct ClientState {
+    /// the tick of the last update the client received in full
+    acked: Tick,
+    /// the entities the client was sent, with the tick they were first sent in full at
+    entities: HashMap<EntityId, Tick>,
+    /// components removed from entities the client has, with the tick of the update they were
+    /// first sent in. Sent until an update at or after that tick is acknowledged.
+    removed: HashMap<EntityId, Vec<(ComponentId, Tick)>>,
+    /// entities that were despawned or left the client's scope, kept like `removed`
+    despawned: BTreeMap<EntityId, Tick>,
+}
+
+impl ClientState {
+    /// Forget the entity, telling the client to despawn it.
+    fn despawn(&mut self, entity: EntityId, tick: Tick) {
+        if self.entities.remove(&entity).is_some() {
+            self.removed.remove(&entity);
+            self.despawned.insert(entity, tick);
+        }
+    }
+}
+
+impl<T: Transport> ReplicationServer<T> {
+    pub fn new(transport: T) -> Self {
+        Self {
+            transport,
+            clients: BTreeMap::new(),
+            tick: 0,
+            max_packet: 1200,
+        }
+    }
+
+    /// Split updates into packets of about this many bytes. Defaults to 1200, which fits
+    /// in a UDP datagram on most networks. An entity is never split across packets.
+    pub fn set_max_packet(&mut self, bytes: usize) {
+        self.max_packet = bytes;
+    }
+
+    /// The engine tick of the last update.
+    pub fn tick(&self) -> Tick {
+        self.tick
+    }
+
+    pub fn transport(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    /// Start sending updates to `peer`, beginning with every replicated entity.
+    /// Clients that send a hello are added by `update`.
+    pub fn add_client(&mut self, peer: PeerId) {
+        self.clients.entry(peer).or_default();
+    }
+
+    pub fn remove_client(&mut self, peer: PeerId) {
+        self.clients.remove(&peer);
+    }
+
+    /// Send every replicated entity to `peer` again on the next update.
+    pub fn resync(&mut self, peer: PeerId) {
+        if let Some(client) = self.clients.get_mut(&peer) {
+            *client = ClientState::default();
+        }
+    }
+
+    pub fn clients(&self) -> impl Iterator<Item = PeerId> + '_ {
+        self.clients.keys().copied()
+    }
+
+    /// Accept new clients and acknowledgements, then send each client what changed since
+    /// the last update it acknowledged. Removals are only kept for two frames, so call
+    /// this at least once a frame.
+    pub fn update(&mut self, engine: &Engine) -> Result<(), ReplicationError> {
+        let mut entities: Vec<EntityId> = engine.archetypes.tables().iter()
+            .filter(|table| table.has(Replicated::__internal_id()))
+            .flat_map(|table| table.ids().iter().copied())
+            .collect();
+        entities.sort_unstable();
+        self.send(engine, |_| entities.clone())
+    }
+
+    /// Like `update`, but each client is only sent the entities in its scope. Entities that
+    /// left it are despawned on the client, and ones that entered it are sent in full. The
+    /// `Parent` and `Children` of entities pointing at one that entered are sent again, since
+    /// the client couldn't map them before.
+    pub fn update_scoped<P: Position>(&mut self, engine: &Engine, interest: &Interest<P>) -> Result<(), ReplicationError> {
+        self.send(engine, |client| {
+            let mut entities: Vec<EntityId> = interest.scope(client).collect();
+            entities.sort_unstable();
+            entities
+        })
+    }
+
+    /// Send each client the changes to the entities `scope` returns for it, in id order.
+    fn send<F>(&mut self, engine: &Engine, scope: F) -> Result<(), ReplicationError>
+    where
+        F: Fn(PeerId) -> Vec<EntityId>
+    {
+        while let Some((peer, packet)) = self.transport.receive()? {
+            match bincode::deserialize(&packet) {
+                // a client saying hello again has restarted, so it gets everything again
+                Ok(Message::Hello) => {
+                    self.clients.insert(peer, ClientState::default());
+                }
+                Ok(Message::Ack(tick)) => {
+                    if let Some(client) = self.clients.get_mut(&peer) {
+                        client.acked = client.acked.max(tick);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let since = self.tick;
+        self.tick = engine.archetypes.tick().advance();
+        let (gone, removed) = removals(engine, since);
+
+        for (peer, client) in self.clients.iter_mut() {
+            for entity in gone.iter() {
+                client.despawn(*entity, self.tick);
+            }
+            for (entity, cmp) in removed.iter() {
+                if client.entities.contains_key(entity) {
+                    client.removed.entry(*entity).or_default().push((*cmp, self.tick));
+                }
+            }
+            let visible = scope(*peer);
+            let left: Vec<EntityId> = client.entities.keys().filter(|id| visible.binary_search(id).is_err()).copied().collect();
+            for entity in left {
+                client.despawn(entity, self.tick);
+            }
+
+            let acked = client.acked;
+            client.despawned.retain(|_, tick| *tick > acked);
+            for removed in client.removed.values_mut() {
+                removed.retain(|(_, tick)| *tick > acked);
+            }
+            client.removed.retain(|_, removed| !removed.is_empty());
+
+            let updates = diff(engine, client, &visible, self.tick, self.max_packet)?;
+            let parts = updates.len() as u32;
+            for (part, mut update) in updates.into_iter().enumerate() {
+                update.part = part as u32;
+                update.parts = parts;
+                self.transport.send(*peer, &bincode::serialize(&Message::Update(update))?)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The replicated entities that were despawned or stopped being `Replicated` after `since`,
+/// and the replicated components removed from the rest.
+fn removals(engine: &Engine, since: Tick) -> (Vec<EntityId>, Vec<(EntityId, ComponentId)>) {
+    let replicated = |entity: EntityId| engine.archetypes.locate(entity)
+        .map(|index| &engine.archetypes.tables()[index.table])
+        .filter(|table| table.has(Replicated::__internal_id()));
+
+    let mut gone: Vec<EntityId> = engine.despawned(since).collect();
+    gone.extend(engine.removed::<Replicated>(since).filter(|entity| replicated(*entity).is_none()));
+
+    let mut removed = Vec::new();
+    for cmp in engine.registry.replicated() {
+        for entity in engine.archetypes.removals().removed(*cmp, since) {
+            // a component inserted again since is sent as changed instead
+            if replicated(entity).is_some_and(|table| !table.has(*cmp)) {
+                removed.push((entity, *cmp));
+            }
+        }
+    }
+    (gone, removed)
+}
+
+/// The updates that bring `client` up to date, split into packets. Entities the client
+/// hasn't acknowledged are sent in full, the rest only with what changed after `client.acked`.
+fn diff(engine: &Engine, client: &mut ClientState, entities: &[EntityId], tick: Tick, max_packet: usize) -> Result<Vec<Update>, ReplicationError> {
+    let mut out = Vec::new();
+    let mut update = Update { tick, ..Update::default() };
+    let mut size = 0;
+
+    let tables = engine.archetypes.tables();
+    let mut located = Vec::with_capacity(entities.len());
+    for id in entities.iter() {
+        if let Some(index) = engine.archetypes.locate(*id).filter(|index| tables[index.table].has(Replicated::__internal_id())) {
+            let sent = match client.entities.get(id) {
+                Some(sent) => *sent,
+                None => {
+                    // it may have left the scope and come back before the client heard it left
+                    client.despawned.remove(id);
+                    client.entities.insert(*id, tick);
+                    tick
+                }
+            };
+            located.push((*id, index, sent > client.acked));
+        }
+    }
+
+    // the hierarchy of an entity the client already has may point at one it is only being
+    // sent now, which the client couldn't map when it got it, so send it again after it.
+    located.sort_by_key(|(id, _, full)| (!full, *id));
+    let mut refresh = HashSet::new();
+    for (id, _, _) in located.iter().filter(|(_, _, full)| *full) {
+        refresh.extend(engine.get::<Parent>(*id).map(|parent| parent.get()));
+        refresh.extend(engine.get::<Children>(*id).into_iter().flat_map(|children| children.iter().copied()));
+    }
+
+    let replicated = engine.registry.replicated();
+    for (id, index, full) in located {
+        let table = &tables[index.table];
+        let col = index.col;
+        let refresh = refresh.contains(&id);
+
+        let mut changed = Vec::new();
+        for cmp in replicated.iter() {
+            let (Some(column), Some(ticks), Some(fns)) = (table.column(*cmp), table.ticks(*cmp), engine.registry.component(*cmp)) else {
+                continue;
+            };
+            let hierarchy = *cmp == Parent::__internal_id() || *cmp == Children::__internal_id();
+            if full || ticks[col] > client.acked || (refresh && hierarchy) {
+                changed.push((*cmp, (fns.serialize)(column, col)?));
+            }
+        }
+        let removed: Vec<ComponentId> = client.removed.get(&id)
+            .map(|removed| removed.iter().map(|(cmp, _)| *cmp).filter(|cmp| !table.has(*cmp)).collect())
+            .unwrap_or_default();
+
+        if !full && changed.is_empty() && removed.is_empty() {
+            continue;
+        }
+
+        let bytes = 16 + changed.iter().map(|(_, data)| data.len() + 16).sum::<usize>() + removed.len() * 8;
+        if size > 0 && size + bytes > max_packet {
+            out.push(std::mem::replace(&mut update, Update { tick, ..Update::default() }));
+            size = 0;
+        }
+        size += bytes;
+        update.entities.push(EntityUpdate { id, changed, removed });
+    }
+
+    update.despawned.extend(client.despawned.keys().copied());
+    if !update.entities.is_empty() || !update.despawned.is_empty() {
+        out.push(update);
+    }
+    Ok(out)
+}
+
+/// Mirrors the entities of a `ReplicationServer` into a client engine. Server
+/// entities are spawned as new entities, and entity ids in components are remapped.
+pub struct ReplicationClient<T: Transport> {
+    transport: T,
+    server: PeerId,
+    map: EntityMap,
+    tick: Tick,
+    /// the packets of the update at `tick` that arrived
+    parts: HashSet<u32>,
+}
+
+impl<T: Transport> ReplicationClient<T> {
+    /// Connect to the server at `server`, asking it for updates.
+    pub fn new(mut transport: T, server: PeerId) -> Result<Self, ReplicationError> {
+        transport.send(server, &bincode::serialize(&Message::Hello)?)?;
+        Ok(Self {
+            transport,
+            server,
+            map: EntityMap::new(),
+            tick: 0,
+            parts: HashSet::new(),
+        })
+    }
+
+    /// The latest server tick that was applied.
+    pub fn tick(&self) -> Tick {
+        self.tick
+    }
+
+    /// The client entity mirroring the server entity `server`.
+    pub fn entity(&self, server: EntityId) -> Option<EntityId> {
+        self.map.get(server)
+    }
+
+    pub fn transport(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    /// Apply every packet that arrived, returning how many, and acknowledge every update
+    /// that arrived in full. Packets older than the latest applied tick arrived out of 
+    /// order and are ignored.
+    pub fn receive(&mut self, engine: &mut Engine) -> Result<usize, ReplicationError> {
+        let mut count = 0;
+        while let Some((peer, packet)) = self.transport.receive()? {
+            if peer != self.server {
+                continue;
+            }
+            if let Message::Update(update) = bincode::deserialize(&packet)? {
+                if update.tick < self.tick {
+                    continue;
+                }
+                if update.tick > self.tick {
+                    self.tick = update.tick;
+                    self.parts.clear();
+                }
+
+                let (tick, part, parts) = (update.tick, update.part, update.parts);
+                self.apply(engine, update)?;
+                count += 1;
+
+                if self.parts.insert(part) && self.parts.len() == parts as usize {
+                    self.transport.send(self.server, &bincode::serialize(&Message::Ack(tick))?)?;
+                }
+            }
+        }
+        Ok(count)
+    }
+
+    fn apply(&mut self, engine: &mut Engine, update: Update) -> Result<(), ReplicationError> {
+        // build every component first, so a bad packet leaves the engine untouched
+        let mut built: Vec<(EntityId, Vec<Anon>, Vec<ComponentId>)> = Vec::with_capacity(update.entities.len());
+        for entity in update.entities {
+            match build_components(engine, &entity.changed) {
+                Ok(anons) => built.push((entity.id, anons, entity.removed)),
+                Err(e) => {
+                    built.iter().for_each(|(_, anons, _)| anons.iter().for_each(|anon| anon.clear()));
+                    return Err(e);
+                }
+            }
+        }
+
+        let mut commands = engine.commands();
+        for (id, _, _) in built.iter() {
+            if self.map.get(*id).is_none() {
+                let mut entity = Entity::new(commands.reserve());
+                entity.insert(Replicated);
+                self.map.insert(*id, entity.id());
+                commands.spawn_entity(entity);
+            }
+        }
+
+        for (id, anons, removed) in built {
+            let local = self.map.map(id);
+            for anon in anons {
+                engine.registry.map_entities(&anon, &self.map);
+                commands.insert_anon(local, anon);
+            }
+            for cmp in removed {
+                commands.remove_id(local, cmp);
+            }
+        }
+
+        for id in update.despawned {
+            if let Some(local) = self.map.remove(id) {
+                commands.destroy(local);
+            }
+        }
+
+        drop(commands);
+        engine.flush();
+        Ok(())
+    }
+}
+
+fn build_components(engine: &Engine, changed: &[(ComponentId, Vec<u8>)]) -> Result<Vec<Anon>, ReplicationError> {
+    let mut out = Vec::with_capacity(changed.len());
+    for (id, data) in changed {
+        let built = match engine.registry.component(*id) {
+            Some(fns) if engine.registry.is_replicated(*id) => (fns.deserialize)(data).map_err(ReplicationError::from),
+            _ => Err(ReplicationError::UnknownComponent(*id)),
+        };
+
+        match built {
+            Ok(anon) => out.push(anon),
+            Err(e) => {
+                out.iter().for_each(|anon| anon.clear());
+                return Err(e);
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// The id of the server on a `LoopbackTransport`.
+pub const LOOPBACK_SERVER: PeerId = 0;
+
+/// An in-process transport, for tests and for running a server and clients in one process.
+/// Nothing is dropped or reordered.
+pub struct LoopbackTransport {
+    id: PeerId,
+    hub: Arc<Mutex<Hub>>,
+}
+
+struct Hub {
+    inboxes: HashMap<PeerId, VecDeque<(PeerId, Vec<u8>)>>,
+    next: PeerId,
+}
+
+impl LoopbackTransport {
+    /// The server end, with id `LOOPBACK_SERVER`.
+    pub fn server() -> Self {
+        let mut inboxes = HashMap::new();
+        inboxes.insert(LOOPBACK_SERVER, VecDeque::new());
+        Self {
+            id: LOOPBACK_SERVER,
+            hub: Arc::new(Mutex::new(Hub { inboxes, next: LOOPBACK_SERVER + 1 })),
+        }
+    }
+
+    /// A new client end connected to the same server.
+    pub fn connect(&self) -> LoopbackTransport {
+        let mut hub = self.hub.lock().unwrap();
+        let id = hub.next;
+        hub.next += 1;
+        hub.inboxes.insert(id, VecDeque::new());
+        Self {
+            id,
+            hub: self.hub.clone(),
+        }
+    }
+
+    pub fn id(&self) -> PeerId {
+        self.id
+    }
+}
+
+impl Transport for LoopbackTransport {
+    fn send(&mut self, peer: PeerId, packet: &[u8]) -> io::Result<()> {
+        match self.hub.lock().unwrap().inboxes.get_mut(&peer) {
+            Some(inbox) => {
+                inbox.push_back((self.id, packet.to_vec()));
+                Ok(())
+            }
+            None => Err(io::Error::new(io::ErrorKind::NotConnected, format!("no loopback peer {}", peer))),
+        }
+    }
+
+    fn receive(&mut self) -> io::Result<Option<(PeerId, Vec<u8>)>> {
+        Ok(self.hub.lock().unwrap().inboxes.get_mut(&self.id).and_then(|inbox| inbox.pop_front()))
+    }
+}
+
+/// A transport over a non-blocking UDP socket. Peers are given ids in the order they are
+/// connected to or first heard from.
+pub struct UdpTransport {
+    socket: UdpSocket,
+    peers: Vec<SocketAddr>,
+    buffer: Vec<u8>,
+}
+
+impl UdpTransport {
+    /// Bind to `addr`, e.g. `127.0.0.1:0` for any free port on localhost.
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            peers: Vec::new(),
+            buffer: vec![0; 65536],
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// The id of the peer at `addr`, adding it if it is new.
+    pub fn connect(&mut self, addr: SocketAddr) -> PeerId {
+        match self.peers.iter().position(|peer| *peer == addr) {
+            Some(id) => id as PeerId,
+            None => {
+                self.peers.push(addr);
+                (self.peers.len() - 1) as PeerId
+            }
+        }
+    }
+
+    pub fn addr(&self, peer: PeerId) -> Option<SocketAddr> {
+        self.peers.get(peer as usize).copied()
+    }
+}
+
+impl Transport for UdpTransport {
+    fn send(&mut self, peer: PeerId, packet: &[u8]) -> io::Result<()> {
+        match self.addr(peer) {
+            Some(addr) => self.socket.send_to(packet, addr).map(|_| ()),
+            None => Err(io::Error::new(io::ErrorKind::NotConnected, format!("no udp peer {}", peer))),
+        }
+    }
+
+    fn receive(&mut self) -> io::Result<Option<(PeerId, Vec<u8>)>> {
+        match self.socket.recv_from(&mut self.buffer) {
+            Ok((len, addr)) => Ok(Some((self.connect(addr), self.buffer[..len].to_vec()))),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transform::{Transform, Vec3};
+
+    #[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+    struct Health(u32);
+
+    impl_component!(Health);
+
+    fn engine() -> Engine {
+        let mut builder = EngineBuilder::new();
+        builder.load_plugin(ReplicationPlugin).register_replicated::<Health>();
+        builder.build()
+    }
+
+    fn connect() -> (ReplicationServer<LoopbackTransport>, ReplicationClient<LoopbackTransport>) {
+        let transport = LoopbackTransport::server();
+        let client = ReplicationClient::new(transport.connect(), LOOPBACK_SERVER).unwrap();
+        (ReplicationServer::new(transport), client)
+    }
+
+    /// Drop every packet waiting for the client, as if the network lost them.
+    fn lose(client: &mut ReplicationClient<LoopbackTransport>) {
+        while client.transport().receive().unwrap().is_some() {}
+    }
+
+    #[test]
+    fn changes_and_removals() {
+        let (mut server, mut client) = connect();
+        let mut host = engine();
+        let mut mirror = engine();
+
+        let entity = host.spawn(|e| { e.insert(Replicated); e.insert(Health(10)); });
+        server.update(&host).unwrap();
+        client.receive(&mut mirror).unwrap();
+        let local = client.entity(entity).unwrap();
+        assert_eq!(mirror.get::<Health>(local), Some(&Health(10)));
+
+        // acknowledged and unchanged, so nothing is sent
+        server.update(&host).unwrap();
+        assert_eq!(client.receive(&mut mirror).unwrap(), 0);
+
+        host.get_mut::<Health>(entity).unwrap().0 = 7;
+        server.update(&host).unwrap();
+        client.receive(&mut mirror).unwrap();
+        assert_eq!(mirror.get::<Health>(local), Some(&Health(7)));
+
+        host.commands().remove::<Health>(entity);
+        host.flush();
+        server.update(&host).unwrap();
+        client.receive(&mut mirror).unwrap();
+        assert!(mirror.get::<Health>(local).is_none());
+
+        host.despawn(entity);
+        server.update(&host).unwrap();
+        client.receive(&mut mirror).unwrap();
+        assert!(client.entity(entity).is_none());
+        assert!(!mirror.contains(local));
+    }
+
+    #[test]
+    fn lost_packets_are_resent() {
+        let (mut server, mut client) = connect();
+        let mut host = engine();
+        let mut mirror = engine();
+
+        let entity = host.spawn(|e| { e.insert(Replicated); e.insert(Health(10)); });
+        server.update(&host).unwrap();
+        lose(&mut client);
+
+        // the spawn was never acknowledged, so it is sent in full again
+        server.update(&host).unwrap();
+        client.receive(&mut mirror).unwrap();
+        let local = client.entity(entity).unwrap();
+        assert_eq!(mirror.get::<Health>(local), Some(&Health(10)));
+
+        host.get_mut::<Health>(entity).unwrap().0 = 3;
+        server.update(&host).unwrap();
+        lose(&mut client);
+        server.update(&host).unwrap();
+        client.receive(&mut mirror).unwrap();
+        assert_eq!(mirror.get::<Health>(local), Some(&Health(3)));
+
+        host.despawn(entity);
+        server.update(&host).unwrap();
+        lose(&mut client);
+        server.update(&host).unwrap();
+        client.receive(&mut mirror).unwrap();
+        assert!(!mirror.contains(local));
+    }
+
+    #[test]
+    fn split_updates_are_acknowledged_once_complete() {
+        let (mut server, mut client) = connect();
+        server.set_max_packet(1);
+        let mut host = engine();
+        let mut mirror = engine();
+
+        let entities: Vec<EntityId> = (0..3).map(|i| host.spawn(|e| { e.insert(Replicated); e.insert(Health(i)); })).collect();
+        server.update(&host).unwrap();
+
+        // lose only the last of the three packets
+        let mut packets = Vec::new();
+        while let Some(packet) = client.transport().receive().unwrap() {
+            packets.push(packet);
+        }
+        assert_eq!(packets.len(), 3);
+        packets.pop();
+        let peer = client.transport().id();
+        for (_, packet) in packets {
+            server.transport().send(peer, &packet).unwrap();
+        }
+        client.receive(&mut mirror).unwrap();
+        assert!(client.entity(entities[2]).is_none());
+
+        server.update(&host).unwrap();
+        client.receive(&mut mirror).unwrap();
+        for (i, entity) in entities.iter().enumerate() {
+            let local = client.entity(*entity).unwrap();
+            assert_eq!(mirror.get::<Health>(local), Some(&Health(i as u32)));
+        }
+    }
+
+    #[test]
+    fn scoped_hierarchy_is_sent_again_when_the_parent_enters() {
+        let (mut server, mut client) = connect();
+        let mut host = engine();
+        let mut mirror = engine();
+        let at = |x: f32| Transform::from_translation(Vec3::new(x, 0.0, 0.0));
+
+        let viewer = host.spawn(|e| { e.insert(Replicated); e.insert(at(0.0)); });
+        let parent = host.spawn(|e| { e.insert(Replicated); e.insert(at(100.0)); });
+        let child = host.spawn(|e| { e.insert(Replicated); e.insert(at(1.0)); });
+        host.commands().entity(child).set_parent(parent);
+        host.flush();
+
+        let mut interest = Interest::<Transform>::new(10.0);
+        interest.set_radius(client.transport().id(), viewer, 5.0);
+        interest.update(&host);
+        server.update_scoped(&host, &interest).unwrap();
+        client.receive(&mut mirror).unwrap();
+        assert!(client.entity(parent).is_none());
+
+        host.get_mut::<Transform>(parent).unwrap().translation.x = 2.0;
+        interest.update(&host);
+        assert_eq!(interest.entered(client.transport().id()), &[parent]);
+        server.update_scoped(&host, &interest).unwrap();
+        client.receive(&mut mirror).unwrap();
+
+        let (local_parent, local_child) = (client.entity(parent).unwrap(), client.entity(child).unwrap());
+        assert_eq!(mirror.get::<Parent>(local_child).unwrap().get(), local_parent);
+        assert!(mirror.get::<Children>(local_parent).unwrap().contains(local_child));
+
+        host.get_mut::<Transform>(parent).unwrap().translation.x = 100.0;
+        interest.update(&host);
+        assert_eq!(interest.left(client.transport().id()), &[parent]);
+        server.update_scoped(&host, &interest).unwrap();
+        client.receive(&mut mirror).unwrap();
+        assert!(!mirror.contains(local_parent));
+    }
+
+    /// Send updates until the client sees `done`, since udp packets
+    /// arrive some time after they are sent.
+    fn sync<T: Transport>(
+        server: &mut ReplicationServer<T>,
+        client: &mut ReplicationClient<T>,
+        host: &Engine,
+        mirror: &mut Engine,
+        done: impl Fn(&ReplicationClient<T>, &Engine) -> bool,
+    ) {
+        for _ in 0..200 {
+            server.update(host).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            client.receive(mirror).unwrap();
+            if done(client, mirror) {
+                return;
+            }
+        }
+        panic!("the client never caught up");
+    }
+
+    #[test]
+    fn udp_round_trip_on_localhost() {
+        let server_transport = UdpTransport::bind("127.0.0.1:0").unwrap();
+        let mut client_transport = UdpTransport::bind("127.0.0.1:0").unwrap();
+        let server_peer = client_transport.connect(server_transport.local_addr().unwrap());
+        let mut client = ReplicationClient::new(client_transport, server_peer).unwrap();
+        let mut server = ReplicationServer::new(server_transport);
+        let mut host = engine();
+        let mut mirror = engine();
+
+        let entity = host.spawn(|e| { e.insert(Replicated); e.insert(Health(10)); });
+        sync(&mut server, &mut client, &host, &mut mirror, |client, _| client.entity(entity).is_some());
+        let local = client.entity(entity).unwrap();
+        assert_eq!(mirror.get::<Health>(local), Some(&Health(10)));
+
+        host.get_mut::<Health>(entity).unwrap().0 = 7;
+        sync(&mut server, &mut client, &host, &mut mirror, |_, mirror| mirror.get::<Health>(local) == Some(&Health(7)));
+    }
+}
@@ -13,6 +13,7 @@ use crate::archetypes::ComponentId;
 use crate::scheduler::Unsafe;
 use crate::archetypes::Archetypes;
 use crate::scheduler::UnsafeRef;
+use crate::scheduler::SystemIndex;
 
 pub type ResourceId = u64;
 
@@ -32,6 +33,15 @@ impl Resources {
         self.resources.insert(R::__internal_id(), Box::new(Unsafe::new(res)));
     }
 
+    /// Move every resource in `other` into this one, replacing any already loaded.
+    pub fn append(&mut self, other: Resources) {
+        self.resources.extend(other.resources);
+    }
+
+    pub fn contains(&self, id: ResourceId) -> bool {
+        self.resources.contains_key(&id)
+    }
+
     pub unsafe fn get<R: Resource>(&self) -> &'static mut R {
         if let Some(res) = self.resources.get(&R::__internal_id()) {
             if let Some(res) = res.downcast_ref::<Unsafe<R>>() {
@@ -49,7 +59,7 @@ pub struct Res<R: Resource>(&'static R);
 pub struct ResMut<R: Resource>(&'static mut R);
 
 impl<R: Resource + 'static> SystemParam for Res<R> {
-    fn fetch_param(engine: UnsafeRef<Engine>) -> Self  {
+    fn fetch_param(engine: UnsafeRef<Engine>, system: SystemIndex) -> Self  {
         Res(unsafe { engine.get().resources.get::<R>() })
     }
 
@@ -71,7 +81,7 @@ impl<R: Resource> Deref for Res<R> {
 }
 
 impl<R: Resource> SystemParam for ResMut<R> {
-    fn fetch_param(engine: UnsafeRef<Engine>) -> Self {
+    fn fetch_param(engine: UnsafeRef<Engine>, system: SystemIndex) -> Self {
         ResMut(unsafe { engine.get().resources.get::<R>() })
     }
 
@@ -96,4 +106,16 @@ impl<R: Resource> DerefMut for ResMut<R> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         self.0
     }
-}
\ No newline at end of file
+}
+/// Implement `Resource` for a type defined inside strata.
+macro_rules! impl_resource {
+    ($t:ident) => {
+        impl strata_traits::Resource for $t {
+            fn __internal_id() -> u64 {
+                $crate::components::id_of(concat!(module_path!(), "::", stringify!($t)))
+            }
+        }
+    };
+}
+
+pub(crate) use impl_resource;
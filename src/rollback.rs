@@ -0,0 +1,103 @@
+use std::any::Any;
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::clone::CloneError;
+use crate::engine::Engine;
+use crate::entity::Entities;
+use crate::index::Indexes;
+use crate::observers::Observers;
+use crate::relations::Relations;
+use crate::resources::ResourceId;
+use crate::table::TableState;
+
+/// A copy of the entities, rollback resources and entity allocator of an engine,
+/// written by `Engine::save_state`. Saving into the same state again reuses its memory.
+pub struct WorldState {
+    pub(crate) tables: Vec<TableState>,
+    pub(crate) entities: Entities,
+    pub(crate) resources: BTreeMap<ResourceId, Option<Box<dyn Any + Send + Sync>>>,
+    pub(crate) relations: Relations,
+    pub(crate) observers: Observers,
+    pub(crate) indexes: Indexes,
+}
+
+impl WorldState {
+    pub fn new() -> Self {
+        Self {
+            tables: Vec::new(),
+            entities: Entities::new(),
+            resources: BTreeMap::new(),
+            relations: Relations::new(),
+            observers: Observers::new(),
+            indexes: Indexes::new(),
+        }
+    }
+}
+
+/// The states of the last few frames, for rolling back when a late input arrives.
+pub struct Rollback {
+    frames: VecDeque<(u64, WorldState)>,
+    capacity: usize,
+}
+
+impl Rollback {
+    /// Keep at most `capacity` frames. Panics if `capacity` is zero.
+    pub fn new(capacity: usize) -> Self {
+        if capacity == 0 {
+            panic!("Attempted to create a rollback buffer with no capacity!");
+        }
+
+        Self {
+            frames: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Save the engine as `frame`, overwriting the oldest frame once full.
+    /// Nothing is overwritten if saving fails.
+    pub fn save(&mut self, engine: &mut Engine, frame: u64) -> Result<(), CloneError> {
+        if self.frames.len() < self.capacity {
+            let mut state = WorldState::new();
+            engine.save_state(&mut state)?;
+            self.frames.push_back((frame, state));
+            return Ok(());
+        }
+
+        // reuse the oldest frame's memory, only moving it to the back once saved
+        engine.save_state(&mut self.frames.front_mut().unwrap().1)?;
+        let (_, state) = self.frames.pop_front().unwrap();
+        self.frames.push_back((frame, state));
+        Ok(())
+    }
+
+    /// Load `frame` into the engine and forget every frame after it, since they
+    /// will be simulated again. Returns false if the frame is no longer stored.
+    pub fn restore(&mut self, engine: &mut Engine, frame: u64) -> bool {
+        match self.frames.iter().position(|(f, _)| *f == frame) {
+            Some(i) => {
+                self.frames.truncate(i + 1);
+                engine.load_state(&self.frames[i].1);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// The most recently saved frame.
+    pub fn latest(&self) -> Option<u64> {
+        self.frames.back().map(|(frame, _)| *frame)
+    }
+
+    /// Every stored frame, oldest first.
+    pub fn frames(&self) -> impl Iterator<Item = u64> + '_ {
+        self.frames.iter().map(|(frame, _)| *frame)
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+use serde::Deserialize;
+use strata_traits::Component;
+
+use crate::anon::Anon;
+use crate::commands::Commands;
+use crate::entity::{Entity, EntityId};
+use crate::hierarchy::{Children, Parent};
+use crate::registry::EntityMap;
+
+/// Entities described in text, spawned with `Scene::spawn` or through a `SceneSpawner`.
+///
+/// ```ron
+/// Scene(
+///     entities: [
+///         (
+///             id: 0,
+///             components: {
+///                 "Transform": (translation: (x: 0.0, y: 1.0, z: 0.0), rotation: (x: 0.0, y: 0.0, z: 0.0, w: 1.0), scale: (x: 1.0, y: 1.0, z: 1.0)),
+///                 "Target": (1),
+///             },
+///             children: [
+///                 (id: 1, components: { "Health": (100) }),
+///             ],
+///         ),
+///     ],
+/// )
+/// ```
+///
+/// Scenes can be read from any serde format, e.g. JSON, as well as with `from_ron`.
+/// Components are looked up by their registered short or full name and must be registered
+/// with `EngineBuilder::register_serializable`. Entity ids stored in components refer to the
+/// `id`s in the scene and are remapped when it is spawned.
+#[derive(Clone, Deserialize)]
+pub struct Scene {
+    entities: Vec<SceneEntity>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct SceneEntity {
+    id: u64,
+    #[serde(default)]
+    components: BTreeMap<String, ron::Value>,
+    #[serde(default)]
+    children: Vec<SceneEntity>,
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Parse(ron::error::SpannedError),
+    DuplicateId(u64),
+    /// No component has this name.
+    UnknownComponent(String),
+    /// The component exists but was not registered as serializable.
+    NotSerializable(String),
+    Component { name: String, error: ron::Error },
+    /// An override names an id that is not in the scene.
+    UnknownEntity(u64),
+    /// No scene was loaded with this name.
+    UnknownScene(String),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Parse(e) => write!(f, "{}", e),
+            SceneError::DuplicateId(id) => write!(f, "scene id {} is used more than once", id),
+            SceneError::UnknownComponent(name) => write!(f, "no component is named {}", name),
+            SceneError::NotSerializable(name) => write!(f, "component {} is not registered as serializable", name),
+            SceneError::Component { name, error } => write!(f, "invalid {}: {}", name, error),
+            SceneError::UnknownEntity(id) => write!(f, "the scene has no entity with id {}", id),
+            SceneError::UnknownScene(name) => write!(f, "no scene named {} has been loaded", name),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl Scene {
+    pub fn from_ron(text: &str) -> Result<Self, SceneError> {
+        ron::from_str(text).map_err(SceneError::Parse)
+    }
+
+    /// Queue every entity in the scene to be spawned, nested entities as children of their parent.
+    pub fn spawn(&self, commands: &mut Commands) -> Result<SceneInstance, SceneError> {
+        self.spawn_with(commands, Overrides::new())
+    }
+
+    /// Spawn the scene with some components replaced or added, e.g. to place
+    /// many instances of a prefab at different positions.
+    pub fn spawn_with(&self, commands: &mut Commands, mut overrides: Overrides) -> Result<SceneInstance, SceneError> {
+        // build every component before reserving ids, so errors leave the engine untouched
+        let mut built: Vec<(u64, Option<u64>, Vec<Anon>)> = Vec::new();
+        let mut seen = HashSet::new();
+        let mut stack: Vec<(&SceneEntity, Option<u64>)> = self.entities.iter().rev().map(|e| (e, None)).collect();
+        while let Some((entity, parent)) = stack.pop() {
+            if !seen.insert(entity.id) {
+                discard(built);
+                return Err(SceneError::DuplicateId(entity.id));
+            }
+
+            match build_components(commands, entity) {
+                Ok(anons) => built.push((entity.id, parent, anons)),
+                Err(e) => {
+                    discard(built);
+                    return Err(e);
+                }
+            }
+            stack.extend(entity.children.iter().rev().map(|child| (child, Some(entity.id))));
+        }
+
+        if let Some(id) = overrides.entries.iter().map(|(id, _)| *id).find(|id| !built.iter().any(|(b, _, _)| b == id)) {
+            discard(built);
+            return Err(SceneError::UnknownEntity(id));
+        }
+
+        let mut map = EntityMap::new();
+        for (id, _, _) in built.iter() {
+            map.insert(EntityId::from_bits(*id), commands.reserve());
+        }
+
+        let mut children: HashMap<u64, Vec<EntityId>> = HashMap::new();
+        for (id, parent, _) in built.iter() {
+            if let Some(parent) = parent {
+                children.entry(*parent).or_default().push(map.map(EntityId::from_bits(*id)));
+            }
+        }
+
+        let mut roots = Vec::new();
+        for (id, parent, anons) in built {
+            let new = map.map(EntityId::from_bits(id));
+            let mut entity = Entity::new(new);
+            for anon in anons {
+                commands.engine().registry.map_entities(&anon, &map);
+                entity.insert_anon(anon);
+            }
+
+            // overrides hold real components, so they are not remapped
+            let mut i = 0;
+            while i < overrides.entries.len() {
+                if overrides.entries[i].0 == id {
+                    entity.insert_anon(overrides.entries.swap_remove(i).1);
+                } else {
+                    i += 1;
+                }
+            }
+
+            match parent {
+                Some(parent) => entity.insert(Parent(map.map(EntityId::from_bits(parent)))),
+                None => roots.push(new),
+            }
+            if let Some(kids) = children.remove(&id) {
+                entity.insert(Children(kids));
+            }
+
+            commands.spawn_entity(entity);
+        }
+
+        Ok(SceneInstance { roots, map })
+    }
+}
+
+fn build_components(commands: &Commands, entity: &SceneEntity) -> Result<Vec<Anon>, SceneError> {
+    let engine = commands.engine();
+    let mut out = Vec::with_capacity(entity.components.len());
+    for (name, value) in entity.components.iter() {
+        let built = match engine.components().get_by_name(name) {
+            None => Err(SceneError::UnknownComponent(name.clone())),
+            Some(info) => match engine.registry.component(info.id()) {
+                None => Err(SceneError::NotSerializable(name.clone())),
+                Some(fns) => (fns.from_value)(value.clone())
+                    .map_err(|error| SceneError::Component { name: name.clone(), error }),
+            },
+        };
+
+        match built {
+            Ok(anon) => out.push(anon),
+            Err(e) => {
+                out.iter().for_each(|anon| anon.clear());
+                return Err(e);
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn discard(built: Vec<(u64, Option<u64>, Vec<Anon>)>) {
+    for (_, _, anons) in built {
+        anons.iter().for_each(|anon| anon.clear());
+    }
+}
+
+/// Components that replace or are added to the entities of a scene when it is spawned.
+pub struct Overrides {
+    entries: Vec<(u64, Anon)>,
+}
+
+impl Overrides {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Give the entity with scene id `id` this component.
+    pub fn insert<C: Component>(mut self, id: u64, cmp: C) -> Self {
+        self.entries.push((id, Anon::new::<C>(cmp)));
+        self
+    }
+}
+
+impl Drop for Overrides {
+    fn drop(&mut self) {
+        self.entries.iter().for_each(|(_, anon)| anon.clear());
+    }
+}
+
+/// The entities spawned from a scene.
+pub struct SceneInstance {
+    roots: Vec<EntityId>,
+    map: EntityMap,
+}
+
+impl SceneInstance {
+    /// The entities that were not nested in another entity.
+    pub fn roots(&self) -> &[EntityId] {
+        &self.roots
+    }
+
+    /// The entity spawned for scene id `id`.
+    pub fn entity(&self, id: u64) -> Option<EntityId> {
+        self.map.get(EntityId::from_bits(id))
+    }
+}
+
+/// Named scenes that can be spawned any number of times, i.e. prefabs.
+pub struct SceneSpawner {
+    scenes: HashMap<String, Scene>,
+}
+
+impl SceneSpawner {
+    pub fn new() -> Self {
+        Self {
+            scenes: HashMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, name: &str, scene: Scene) {
+        self.scenes.insert(name.to_string(), scene);
+    }
+
+    pub fn load_ron(&mut self, name: &str, text: &str) -> Result<(), SceneError> {
+        self.insert(name, Scene::from_ron(text)?);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Scene> {
+        self.scenes.get(name)
+    }
+
+    /// Spawn the scene called `name`.
+    pub fn spawn(&self, commands: &mut Commands, name: &str) -> Result<SceneInstance, SceneError> {
+        self.spawn_with(commands, name, Overrides::new())
+    }
+
+    pub fn spawn_with(&self, commands: &mut Commands, name: &str, overrides: Overrides) -> Result<SceneInstance, SceneError> {
+        match self.scenes.get(name) {
+            Some(scene) => scene.spawn_with(commands, overrides),
+            None => Err(SceneError::UnknownScene(name.to_string())),
+        }
+    }
+}
@@ -1,4 +1,5 @@
 
+use std::ops::Range;
 use std::sync::Arc;
 
 use rayon::prelude::*;
@@ -13,27 +14,40 @@ use crate::archetypes::Archetypes;
 
 pub type SystemIndex = usize;
 
+#[derive(Clone)]
 pub struct Scheduler {
     systems: Vec<Node>,
+    /// indices of the first system after each `apply_deferred`
+    barriers: Vec<SystemIndex>,
 }
 
 impl Scheduler {
     pub fn new() -> Self {
         Self {
             systems: Vec::new(),
+            barriers: Vec::new(),
         }
     }
 
+    /// Flush commands before any system inserted after this point runs.
+    pub fn apply_deferred(&mut self) {
+        self.barriers.push(self.systems.len());
+    }
+
     pub fn insert(&mut self, system: Box<dyn System + Send + Sync>) {
+        let access = system.accessors();
         let mut new = Node {
-            access: system.accessors(),
+            commands: access.contains(&Accessor::Commands),
+            access,
             system: Arc::new(system),
             edges: vec![self.systems.len()],
             has_ran: Unsafe::new(false),
         };
 
+        // systems never run alongside systems on the other side of a barrier
         let index = self.systems.len();
-        for (i, node) in self.systems.iter_mut().enumerate() {
+        let start = self.barriers.last().copied().unwrap_or(0);
+        for (i, node) in self.systems.iter_mut().enumerate().skip(start) {
             if !node.conflicts_with(&new) {
                 new.edges.push(i);
                 node.edges.push(index);
@@ -43,21 +57,44 @@ impl Scheduler {
         self.systems.push(new);
     }
 
-    pub fn execute(&mut self, engine: UnsafeRef<Engine>) {
+    /// The systems between each `apply_deferred`, in order. The commands of each segment 
+    /// are applied before the next one runs, see `Engine::run_schedule`.
+    pub fn segments(&self) -> Vec<Range<SystemIndex>> {
+        let mut out = Vec::new();
+        let mut start = 0;
+        for end in self.barriers.iter().copied().chain(std::iter::once(self.systems.len())) {
+            if end > start {
+                out.push(start..end);
+            }
+            start = end;
+        }
+        out
+    }
+
+    /// Run the systems in `segment`. Their commands are buffered, not applied.
+    /// In strict mode systems that use `Commands` run alone, in index order.
+    pub fn execute(&self, segment: Range<SystemIndex>, engine: UnsafeRef<Engine>, strict: bool) {
         // for each system
-        for i in 0..self.systems.len() {
+        for i in segment.clone() {
             // if the system has not been ran
             if !self.systems[i].has_ran() {
+                if strict && self.systems[i].commands {
+                    self.systems[i].system.execute(engine.clone(), i);
+                    self.systems[i].set_has_ran(true);
+                    continue;
+                }
+
                 // run each system at each edge (with which it has no conflicts)
                 rayon::scope(|s| {
                     // for each edge
                     for edge in self.systems[i].edges.iter() {
                         // if the system has not been ran,
-                        if !self.systems[*edge].has_ran() {
+                        if !self.systems[*edge].has_ran() && !(strict && self.systems[*edge].commands) {
                             // run it.
                             let sys = self.systems[*edge].system.clone();
                             let eng = engine.clone();
-                            s.spawn(move |_| sys.execute(eng));
+                            let index = *edge;
+                            s.spawn(move |_| sys.execute(eng, index));
                             self.systems[*edge].set_has_ran(true);
                         }
                     }
@@ -65,7 +102,7 @@ impl Scheduler {
             }
         }
 
-        for node in self.systems.iter_mut() {
+        for node in self.systems[segment].iter() {
             node.set_has_ran(false);
         }
     }
@@ -80,10 +117,26 @@ impl Scheduler {
 struct Node {
     system: Arc<Box<dyn System + Send + Sync>>,
     access: Vec<Accessor>,
+    /// whether the system takes `Commands`
+    commands: bool,
     edges: Vec<SystemIndex>,
     has_ran: Unsafe<bool>,
 }
 
+/// Systems are shared between clones, which run them independently. This relies on
+/// systems being stateless, see `System`.
+impl Clone for Node {
+    fn clone(&self) -> Self {
+        Self {
+            system: self.system.clone(),
+            access: self.access.clone(),
+            commands: self.commands,
+            edges: self.edges.clone(),
+            has_ran: Unsafe::new(false),
+        }
+    }
+}
+
 impl Node {
     pub fn conflicts_with(&self, other: &Node) -> bool {
         for accessor in self.access.iter() {
@@ -108,7 +161,7 @@ impl Node {
                         return true
                     }
                 },
-                Accessor::None => { /* do nothing */ }
+                Accessor::None | Accessor::Commands => { /* do nothing */ }
             }
         }
 
@@ -131,6 +184,8 @@ impl Node {
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
 pub enum Accessor {
     None,
+    /// the system queues `Commands`
+    Commands,
     Ref(ComponentId),
     Mut(ComponentId),
     Res(ResourceId),
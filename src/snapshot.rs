@@ -0,0 +1,235 @@
+use std::fmt;
+
+use crate::archetypes::ComponentId;
+use crate::commands::Queue;
+use crate::engine::Engine;
+use crate::entity::{Entity, EntityId};
+use crate::registry::EntityMap;
+use crate::resources::{ResourceId, Resources};
+
+const MAGIC: [u8; 4] = *b"STRS";
+
+/// Bumped whenever the layout below changes.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+// Layout, all integers little endian:
+//
+//   magic [u8; 4], version u32
+//   resources: count u32, then per resource: id u64, len u32, bytes
+//   tables:    count u32, then per table:
+//     component count u32, component ids u64..
+//     entity count u32, then per entity: id u64, then per component: len u32, bytes
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    /// The data does not start with the snapshot magic bytes.
+    NotASnapshot,
+    UnsupportedVersion(u32),
+    /// A component in the snapshot is not registered as serializable in this engine.
+    UnknownComponent(ComponentId),
+    /// A resource in the snapshot is not registered as serializable in this engine.
+    UnknownResource(ResourceId),
+    UnexpectedEnd,
+    Bincode(bincode::Error),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::NotASnapshot => write!(f, "not a strata snapshot"),
+            SnapshotError::UnsupportedVersion(v) => write!(f, "unsupported snapshot version {} (expected {})", v, SNAPSHOT_VERSION),
+            SnapshotError::UnknownComponent(id) => write!(f, "component {:#x} is not registered as serializable", id),
+            SnapshotError::UnknownResource(id) => write!(f, "resource {:#x} is not registered as serializable", id),
+            SnapshotError::UnexpectedEnd => write!(f, "snapshot ended unexpectedly"),
+            SnapshotError::Bincode(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<bincode::Error> for SnapshotError {
+    fn from(e: bincode::Error) -> Self {
+        SnapshotError::Bincode(e)
+    }
+}
+
+/// Write every entity with its serializable components, and every serializable resource.
+/// Entities without any serializable components are not written.
+pub(crate) fn save(engine: &Engine) -> Result<Vec<u8>, SnapshotError> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&MAGIC);
+    write_u32(&mut out, SNAPSHOT_VERSION);
+
+    let resources: Vec<_> = engine.registry.resources().into_iter()
+        .filter(|(id, _)| engine.resources.contains(*id))
+        .collect();
+    write_u32(&mut out, resources.len() as u32);
+    for (id, fns) in resources {
+        write_u64(&mut out, id);
+        write_bytes(&mut out, &(fns.serialize)(&engine.resources)?);
+    }
+
+    // entities with nothing to save, like observers, are left out 
+    // rather than coming back as empty entities.
+    let tables: Vec<_> = engine.archetypes.tables().iter()
+        .filter(|table| !table.is_empty())
+        .map(|table| {
+            let columns: Vec<_> = table.archetype().ids().iter()
+                .filter_map(|id| Some((table.column(*id)?, engine.registry.component(*id)?)))
+                .collect();
+            (table, columns)
+        })
+        .filter(|(_, columns)| !columns.is_empty())
+        .collect();
+    write_u32(&mut out, tables.len() as u32);
+    for (table, columns) in tables {
+        write_u32(&mut out, columns.len() as u32);
+        for (column, _) in columns.iter() {
+            write_u64(&mut out, column.id());
+        }
+
+        write_u32(&mut out, table.len() as u32);
+        for (col, id) in table.ids().iter().enumerate() {
+            write_u64(&mut out, id.to_bits());
+            for (column, fns) in columns.iter() {
+                write_bytes(&mut out, &(fns.serialize)(column, col)?);
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+/// Spawn every entity in a snapshot as a new entity and overwrite the resources in it.
+/// Returns the ids the entities had when saved mapped to their new ids.
+pub(crate) fn load(engine: &mut Engine, bytes: &[u8]) -> Result<EntityMap, SnapshotError> {
+    let mut reader = Reader { bytes, pos: 0 };
+    if reader.take(4)? != MAGIC {
+        return Err(SnapshotError::NotASnapshot);
+    }
+    let version = reader.u32()?;
+    if version != SNAPSHOT_VERSION {
+        return Err(SnapshotError::UnsupportedVersion(version));
+    }
+
+    // check the whole layout before touching the engine
+    let mut resources = Vec::new();
+    for _ in 0..reader.u32()? {
+        let id = reader.u64()?;
+        let fns = engine.registry.resource(id).ok_or(SnapshotError::UnknownResource(id))?;
+        resources.push((*fns, reader.bytes()?));
+    }
+
+    let mut saved: Vec<(EntityId, Vec<(ComponentId, &[u8])>)> = Vec::new();
+    for _ in 0..reader.u32()? {
+        let mut ids = Vec::new();
+        for _ in 0..reader.u32()? {
+            let id = reader.u64()?;
+            if engine.registry.component(id).is_none() {
+                return Err(SnapshotError::UnknownComponent(id));
+            }
+            ids.push(id);
+        }
+
+        for _ in 0..reader.u32()? {
+            let entity = EntityId::from_bits(reader.u64()?);
+            let mut components = Vec::with_capacity(ids.len());
+            for id in ids.iter() {
+                components.push((*id, reader.bytes()?));
+            }
+            saved.push((entity, components));
+        }
+    }
+
+    let mut entities = Vec::with_capacity(saved.len());
+    for (old, components) in saved {
+        let mut entity = Entity::new(EntityId::DANGLING);
+        for (id, data) in components {
+            let fns = engine.registry.component(id).unwrap();
+            match (fns.deserialize)(data) {
+                Ok(anon) => entity.insert_anon(anon),
+                Err(e) => {
+                    entities.push((old, entity));
+                    discard(entities);
+                    return Err(e.into());
+                }
+            }
+        }
+        entities.push((old, entity));
+    }
+
+    // resources are only swapped in once every one of them deserialized
+    let mut staged = Resources::new();
+    for (fns, data) in resources {
+        if let Err(e) = (fns.deserialize)(&mut staged, data) {
+            discard(entities);
+            return Err(e.into());
+        }
+    }
+    engine.resources.append(staged);
+
+    let mut map = EntityMap::new();
+    for (old, entity) in entities.iter_mut() {
+        entity.id = engine.archetypes.reserve();
+        map.insert(*old, entity.id);
+    }
+
+    let mut queue = Queue::default();
+    for (_, entity) in entities {
+        for anon in entity.components.iter() {
+            engine.registry.map_entities(anon, &map);
+        }
+        queue.spawn(entity);
+    }
+    engine.archetypes.queue(queue);
+    engine.flush();
+
+    Ok(map)
+}
+
+fn discard(entities: Vec<(EntityId, Entity)>) {
+    for (_, entity) in entities {
+        entity.components.iter().for_each(|anon| anon.clear());
+    }
+}
+
+fn write_u32(out: &mut Vec<u8>, v: u32) {
+    out.extend_from_slice(&v.to_le_bytes());
+}
+
+fn write_u64(out: &mut Vec<u8>, v: u64) {
+    out.extend_from_slice(&v.to_le_bytes());
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_u32(out, bytes.len() as u32);
+    out.extend_from_slice(bytes);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+        let end = self.pos.checked_add(len).ok_or(SnapshotError::UnexpectedEnd)?;
+        let out = self.bytes.get(self.pos..end).ok_or(SnapshotError::UnexpectedEnd)?;
+        self.pos = end;
+        Ok(out)
+    }
+
+    fn u32(&mut self) -> Result<u32, SnapshotError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, SnapshotError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], SnapshotError> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+}
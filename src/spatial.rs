@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+use crate::builder::{EngineBuilder, Plugin};
+use crate::changes::{Tick, Ticks};
+use crate::entity::EntityId;
+use crate::interest::Position;
+use crate::query::{Query, Ref};
+use crate::resources::{impl_resource, ResMut};
+use crate::systems::Stage;
+use crate::transform::Vec3;
+
+type Cell = (i32, i32, i32);
+
+/// Entities bucketed into a uniform grid by position, for proximity queries. Kept in sync
+/// with a position component by `SpatialPlugin`, once per frame in `Stage::Late`, so
+/// entities destroyed since may still be returned. Check them with `Query::get`.
+pub struct SpatialIndex {
+    cell_size: f32,
+    cells: HashMap<Cell, Vec<EntityId>>,
+    entries: HashMap<EntityId, Entry>,
+    /// the tick of the last `update_spatial_index`
+    tick: Tick,
+}
+
+impl_resource!(SpatialIndex);
+
+struct Entry {
+    position: Vec3,
+    cell: Cell,
+}
+
+impl SpatialIndex {
+    /// Panics if `cell_size` is not positive.
+    pub fn new(cell_size: f32) -> Self {
+        if cell_size.is_nan() || cell_size <= 0.0 {
+            panic!("Attempted to create a spatial index with cell size {}!", cell_size);
+        }
+
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+            entries: HashMap::new(),
+            tick: 0,
+        }
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Every indexed entity.
+    pub fn entities(&self) -> impl Iterator<Item = EntityId> + '_ {
+        self.entries.keys().copied()
+    }
+
+    /// The position `entity` had when it was last indexed.
+    pub fn position(&self, entity: EntityId) -> Option<Vec3> {
+        self.entries.get(&entity).map(|entry| entry.position)
+    }
+
+    /// Index `entity` at `position`, moving it if it was already indexed.
+    pub fn insert(&mut self, entity: EntityId, position: Vec3) {
+        let cell = self.cell_of(position);
+        match self.entries.get_mut(&entity) {
+            Some(entry) => {
+                entry.position = position;
+                if entry.cell != cell {
+                    let old = std::mem::replace(&mut entry.cell, cell);
+                    remove_from(&mut self.cells, old, entity);
+                    self.cells.entry(cell).or_default().push(entity);
+                }
+            }
+            None => {
+                self.entries.insert(entity, Entry { position, cell });
+                self.cells.entry(cell).or_default().push(entity);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, entity: EntityId) -> bool {
+        match self.entries.remove(&entity) {
+            Some(entry) => {
+                remove_from(&mut self.cells, entry.cell, entity);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Every entity within `radius` of `center`.
+    pub fn query_radius(&self, center: Vec3, radius: f32) -> Vec<EntityId> {
+        let extent = Vec3::new(radius, radius, radius);
+        let mut out = Vec::new();
+        self.visit(center - extent, center + extent, |entity, position| {
+            let offset = position - center;
+            if offset.dot(offset) <= radius * radius {
+                out.push(entity);
+            }
+        });
+        out
+    }
+
+    /// Every entity inside the box from `min` to `max`, inclusive.
+    pub fn query_aabb(&self, min: Vec3, max: Vec3) -> Vec<EntityId> {
+        let mut out = Vec::new();
+        self.visit(min, max, |entity, p| {
+            if p.x >= min.x && p.y >= min.y && p.z >= min.z && p.x <= max.x && p.y <= max.y && p.z <= max.z {
+                out.push(entity);
+            }
+        });
+        out
+    }
+
+    /// The `k` entities closest to `center`, closest first.
+    pub fn nearest_k(&self, center: Vec3, k: usize) -> Vec<EntityId> {
+        let mut found: Vec<(f32, EntityId)> = Vec::new();
+        if k == 0 || self.entries.is_empty() {
+            return Vec::new();
+        }
+
+        // search shells of cells around the center until nothing further out can be closer
+        let origin = self.cell_of(center);
+        let mut ring = 0;
+        loop {
+            let shell = (2 * ring + 1) as usize;
+            if shell * shell * shell > self.cells.len() * 8 {
+                // the shells got bigger than the grid, so check everything left at once
+                found = self.entries.iter()
+                    .map(|(entity, entry)| (distance_squared(entry.position, center), *entity))
+                    .collect();
+                break;
+            }
+
+            for cell in shell_cells(origin, ring) {
+                for entity in self.cells.get(&cell).into_iter().flatten() {
+                    found.push((distance_squared(self.entries[entity].position, center), *entity));
+                }
+            }
+
+            // anything outside this shell is at least `ring` cells away
+            let reach = ring as f32 * self.cell_size;
+            if found.len() >= k {
+                found.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+                if found[k - 1].0 <= reach * reach {
+                    break;
+                }
+            }
+            if found.len() == self.entries.len() {
+                break;
+            }
+            ring += 1;
+        }
+
+        found.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+        found.into_iter().take(k).map(|(_, entity)| entity).collect()
+    }
+
+    /// Call `f` with every entity in a cell overlapping the box from `min` to `max`.
+    fn visit<F: FnMut(EntityId, Vec3)>(&self, min: Vec3, max: Vec3, mut f: F) {
+        let (lo, hi) = (self.cell_of(min), self.cell_of(max));
+        let span = |a: i32, b: i32| (b as i64 - a as i64 + 1).max(0) as u64;
+        let count = span(lo.0, hi.0).saturating_mul(span(lo.1, hi.1)).saturating_mul(span(lo.2, hi.2));
+
+        let inside = |cell: &Cell| {
+            cell.0 >= lo.0 && cell.1 >= lo.1 && cell.2 >= lo.2 && cell.0 <= hi.0 && cell.1 <= hi.1 && cell.2 <= hi.2
+        };
+        let mut visit_cell = |entities: &Vec<EntityId>| {
+            for entity in entities {
+                f(*entity, self.entries[entity].position);
+            }
+        };
+
+        // a big box is cheaper to check against the occupied cells
+        if count > self.cells.len() as u64 {
+            self.cells.iter().filter(|(cell, _)| inside(cell)).for_each(|(_, entities)| visit_cell(entities));
+        } else {
+            for x in lo.0..=hi.0 {
+                for y in lo.1..=hi.1 {
+                    for z in lo.2..=hi.2 {
+                        if let Some(entities) = self.cells.get(&(x, y, z)) {
+                            visit_cell(entities);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn cell_of(&self, position: Vec3) -> Cell {
+        let cell = |v: f32| (v / self.cell_size).floor() as i32;
+        (cell(position.x), cell(position.y), cell(position.z))
+    }
+}
+
+fn remove_from(cells: &mut HashMap<Cell, Vec<EntityId>>, cell: Cell, entity: EntityId) {
+    if let Some(entities) = cells.get_mut(&cell) {
+        if let Some(i) = entities.iter().position(|e| *e == entity) {
+            entities.swap_remove(i);
+        }
+        if entities.is_empty() {
+            cells.remove(&cell);
+        }
+    }
+}
+
+fn distance_squared(a: Vec3, b: Vec3) -> f32 {
+    let offset = a - b;
+    offset.dot(offset)
+}
+
+/// The cells exactly `ring` cells away from `origin` along some axis.
+fn shell_cells(origin: Cell, ring: i32) -> impl Iterator<Item = Cell> {
+    (-ring..=ring).flat_map(move |x| (-ring..=ring).flat_map(move |y| (-ring..=ring).map(move |z| (x, y, z))))
+        .filter(move |(x, y, z)| x.abs().max(y.abs()).max(z.abs()) == ring)
+        .map(move |(x, y, z)| (origin.0 + x, origin.1 + y, origin.2 + z))
+}
+
+/// Keeps a `SpatialIndex` of every entity with the position component `P`.
+pub struct SpatialPlugin<P: Position> {
+    cell_size: f32,
+    marker: PhantomData<fn() -> P>,
+}
+
+impl<P: Position> SpatialPlugin<P> {
+    /// Cells about the size of the usual query radius work best.
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<P: Position> Plugin for SpatialPlugin<P> {
+    fn build(&self, builder: &mut EngineBuilder) {
+        builder
+            .load_resource(SpatialIndex::new(self.cell_size))
+            .load_system(update_spatial_index::<P>, Stage::Late);
+    }
+}
+
+/// Move entities whose position changed since the last run and drop the ones that lost it.
+pub fn update_spatial_index<P: Position>(mut index: ResMut<SpatialIndex>, ticks: Ticks, positions: Query<(Ref<P>,)>) {
+    let since = index.tick;
+    index.tick = ticks.advance();
+
+    // despawned entities count as removed too
+    for entity in ticks.removed::<P>(since) {
+        index.remove(entity);
+    }
+    for (position, entity) in positions.iter().filter(|(position, _)| position.last_changed() > since) {
+        // only entities that moved to another cell touch the grid
+        index.insert(entity, position.position());
+    }
+}
@@ -8,12 +8,15 @@ use crate::scheduler::Accessor;
 use crate::archetypes::ComponentId;
 use crate::scheduler::Scheduler;
 use crate::scheduler::UnsafeRef;
+use crate::scheduler::SystemIndex;
 use crate::resources::Resources;
 use crate::archetypes::Archetypes;
 
+#[derive(Clone)]
 pub struct Systems {
     startup: Vec<Scheduler>,
     systems: Vec<Scheduler>,
+    strict: bool,
 }
 
 impl Systems {
@@ -33,27 +36,45 @@ impl Systems {
                 Scheduler::new(),
                 Scheduler::new(),
             ],
+            strict: false,
         }
     }
 
+    /// Run systems that use `Commands` one at a time, in the order they were loaded.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
     pub fn load_startup(&mut self, system: Box<dyn System + Send + Sync>, stage: Stage) {
-        self.systems[stage.index()].insert(system)
+        self.startup[stage.index()].insert(system)
     }
 
     pub fn load_system(&mut self, system: Box<dyn System + Send + Sync>, stage: Stage) {
         self.systems[stage.index()].insert(system)
     }
 
-    pub fn execute_startup(&mut self, engine: UnsafeRef<Engine>) {
-        for scheduler in self.startup.iter_mut() {
-            scheduler.execute(engine.clone());
-        }
+    /// Apply queued commands between the systems loaded into `stage` 
+    /// before this call and the ones loaded after it.
+    pub fn apply_deferred(&mut self, stage: Stage) {
+        self.systems[stage.index()].apply_deferred()
     }
 
-    pub fn execute_systems(&mut self, engine: UnsafeRef<Engine>) {
-        for scheduler in self.systems.iter_mut() {
-            scheduler.execute(engine.clone());
-        }
+    pub fn apply_deferred_startup(&mut self, stage: Stage) {
+        self.startup[stage.index()].apply_deferred()
+    }
+
+    /// The startup schedule of each stage, in stage order.
+    pub fn startup(&self) -> &[Scheduler] {
+        &self.startup
+    }
+
+    /// The schedule of each stage, in stage order.
+    pub fn systems(&self) -> &[Scheduler] {
+        &self.systems
     }
 
     pub fn get_queries(&mut self, queries: &mut Vec<Vec<ComponentId>>) {
@@ -84,8 +105,11 @@ impl Stage {
     }
 }
 
+/// Systems should keep their state in resources and components. A system is shared
+/// by an engine and its forks, so anything it captures, e.g. a `Mutex` in a closure,
+/// is shared too and is not rolled back.
 pub trait System: 'static {
-    fn execute(&self, engine: UnsafeRef<Engine>);
+    fn execute(&self, engine: UnsafeRef<Engine>, system: SystemIndex);
     fn accessors(&self) -> Vec<Accessor>;
     fn queries(&self, queries: &mut Vec<Vec<ComponentId>>);
 }
@@ -126,8 +150,8 @@ impl<F, Params: SystemParam> System for FunctionSystem<F, Params>
 where
     F: SystemParamFunction<Params>,
 {
-    fn execute(&self, engine: UnsafeRef<Engine>) {
-        SystemParamFunction::execute(&self.system, engine);
+    fn execute(&self, engine: UnsafeRef<Engine>, system: SystemIndex) {
+        SystemParamFunction::execute(&self.system, engine, system);
     }
 
     fn accessors(&self) -> Vec<Accessor> {
@@ -141,14 +165,15 @@ where
 
 /// Function with only system params
 trait SystemParamFunction<Params: SystemParam>: 'static {
-    fn execute(&self, engine: UnsafeRef<Engine>);
+    fn execute(&self, engine: UnsafeRef<Engine>, system: SystemIndex);
     fn accessors(&self) -> Vec<Accessor>;
     fn fetch_queries(&self, queries: &mut Vec<Vec<ComponentId>>);
 }
 
 /// Marker Trait for parameters of a system function
 pub trait SystemParam: 'static {
-    fn fetch_param(engine: UnsafeRef<Engine>) -> Self;
+    /// `system` is the index of the system being run within its stage.
+    fn fetch_param(engine: UnsafeRef<Engine>, system: SystemIndex) -> Self;
     fn fetch_access() -> Vec<Accessor>;
     fn fetch_queries(queries: &mut Vec<Vec<ComponentId>>);
 }
@@ -172,8 +197,8 @@ pub mod macros {
                 F: Fn($($p),*) -> () + 'static,
                 $($p: SystemParam),*
             {
-                fn execute(&self, engine: UnsafeRef<Engine>) {
-                    self($(<$p as SystemParam>::fetch_param(engine.clone())),*)
+                fn execute(&self, engine: UnsafeRef<Engine>, system: SystemIndex) {
+                    self($(<$p as SystemParam>::fetch_param(engine.clone(), system)),*)
                 }
 
                 fn accessors(&self) -> Vec<Accessor> {
@@ -192,9 +217,9 @@ pub mod macros {
             where
                 $($p: SystemParam),*
             {
-                fn fetch_param(engine: UnsafeRef<Engine>) -> Self {
+                fn fetch_param(engine: UnsafeRef<Engine>, system: SystemIndex) -> Self {
                     (
-                        $(<$p as SystemParam>::fetch_param(engine.clone())),*,
+                        $(<$p as SystemParam>::fetch_param(engine.clone(), system)),*,
                     )
                 }
 
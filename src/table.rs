@@ -2,17 +2,26 @@
 use std::sync::{Mutex, MutexGuard};
 use std::cell::UnsafeCell;
 use std::collections::BTreeMap;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use indexmap::IndexMap;
 use strata_traits::Component;
 
-use crate::archetypes::{ComponentId, Column};
+use crate::archetypes::{Archetype, ComponentId, Column, TableIndex};
 use crate::anon::{AnonVec, Anon, AnonIter};
-use crate::entity::{Entity, EntityIndexIter};
+use crate::entity::{Entity, EntityId, EntityIndex, EntityIndexIter, Entities};
+use crate::components::Components;
+use crate::hooks::{HookEvent, HookKind};
+use crate::changes::{Removals, Tick};
+use crate::commands::merge_modify;
 
 pub struct Table {
     rows: BTreeMap<ComponentId, AnonVec>,
+    /// the tick each component was last inserted or changed at, parallel to `rows`
+    ticks: BTreeMap<ComponentId, Vec<Tick>>,
+    ids: Vec<EntityId>,
+    archetype: Archetype,
+    pub(crate) edges: Edges,
     queue: Mutex<Queues>,
     update: UnsafeCell<bool>,
     modify: UnsafeCell<bool>,
@@ -20,16 +29,28 @@ pub struct Table {
 }
 
 impl Table {
-    pub fn new(mut entity: Entity) -> Self {
+    pub fn new(mut entity: Entity, tick: Tick) -> Self {
+        let id = entity.id;
         let mut rows = BTreeMap::new();
+        let mut ticks = BTreeMap::new();
         while let Some(anon) = entity.pop() {
+            ticks.insert(anon.id(), vec![tick]);
             if let Some(_) = rows.insert(anon.id(), AnonVec::new(anon)) {
                 panic!("Archetypes can only contain one of each component")
             }
         }
 
+        let mut archetype = Archetype::new();
+        for id in rows.keys() {
+            archetype.add(*id);
+        }
+
         Self {
             rows,
+            ticks,
+            ids: vec![id],
+            archetype,
+            edges: Edges::new(),
             queue: Mutex::new(Queues::new()),
             update: UnsafeCell::new(false),
             modify: UnsafeCell::new(false),
@@ -37,6 +58,47 @@ impl Table {
         }
     }
 
+    /// Create a table with no entities from a set of empty columns.
+    pub fn with_columns(columns: Vec<AnonVec>) -> Self {
+        let mut rows = BTreeMap::new();
+        for column in columns {
+            if let Some(_) = rows.insert(column.id(), column) {
+                panic!("Archetypes can only contain one of each component")
+            }
+        }
+
+        let mut archetype = Archetype::new();
+        for id in rows.keys() {
+            archetype.add(*id);
+        }
+
+        Self {
+            ticks: rows.keys().map(|id| (*id, Vec::new())).collect(),
+            rows,
+            ids: Vec::new(),
+            archetype,
+            edges: Edges::new(),
+            queue: Mutex::new(Queues::new()),
+            update: UnsafeCell::new(false),
+            modify: UnsafeCell::new(false),
+            num_entities: 0,
+        }
+    }
+
+    /// The archetype of the entities stored in this table.
+    pub fn archetype(&self) -> &Archetype {
+        &self.archetype
+    }
+
+    /// Empty copies of every column, used to build neighbouring tables.
+    pub fn empty_columns(&self) -> Vec<AnonVec> {
+        self.rows.values().map(|row| row.empty_like()).collect()
+    }
+
+    pub fn has(&self, id: ComponentId) -> bool {
+        self.rows.contains_key(&id)
+    }
+
     pub fn needs_update(&self) -> bool {
         unsafe { *self.update.get() }
     }
@@ -49,7 +111,144 @@ impl Table {
         self.num_entities == 0
     }
 
-    pub fn contains(&self, ids: &Vec<ComponentId>) -> bool {
+    pub fn len(&self) -> usize {
+        self.num_entities
+    }
+
+    /// The id of the entity in each column.
+    pub fn ids(&self) -> &[EntityId] {
+        &self.ids
+    }
+
+    pub fn column(&self, id: ComponentId) -> Option<&AnonVec> {
+        self.rows.get(&id)
+    }
+
+    /// The column holding `id`, stamping the entity at `col` as changed.
+    pub fn column_mut(&mut self, id: ComponentId, col: Column, tick: Tick) -> Option<&mut AnonVec> {
+        self.ticks.get_mut(&id)?[col] = tick;
+        self.rows.get_mut(&id)
+    }
+
+    pub fn get<C: Component>(&self, col: Column) -> Option<&C> {
+        self.rows.get(&C::__internal_id()).map(|row| row.get_as::<C>(col))
+    }
+
+    /// A component and its change tick, for queries that hand out `'static` references.
+    pub fn fetch<C: Component>(&self, col: Column) -> Option<(&'static mut C, &'static mut Tick)> {
+        let row = self.rows.get(&C::__internal_id())?;
+        let ticks = self.ticks.get(&C::__internal_id())?;
+        let tick = unsafe { &mut *(ticks.as_ptr() as *mut Tick).add(col) };
+        Some((row.fetch_as::<C>(col), tick))
+    }
+
+    /// A component, marked as changed at `tick`.
+    pub fn get_mut<C: Component>(&mut self, col: Column, tick: Tick) -> Option<&mut C> {
+        self.ticks.get_mut(&C::__internal_id())?[col] = tick;
+        self.rows.get_mut(&C::__internal_id()).map(|row| row.get_mut_as::<C>(col))
+    }
+
+    /// The tick the component `id` of the entity at `col` was last inserted or changed at.
+    pub fn tick(&self, id: ComponentId, col: Column) -> Option<Tick> {
+        self.ticks.get(&id).map(|ticks| ticks[col])
+    }
+
+    /// The change ticks of `id`, one per entity.
+    pub fn ticks(&self, id: ComponentId) -> Option<&[Tick]> {
+        self.ticks.get(&id).map(|ticks| ticks.as_slice())
+    }
+
+    /// The names of the components in this table that were not registered as cloneable.
+    pub fn not_cloneable(&self, components: &Components) -> Vec<String> {
+        self.rows.keys()
+            .filter(|id| components.get(**id).and_then(|info| info.clone_fn()).is_none())
+            .map(|id| components.name(*id))
+            .collect()
+    }
+
+    /// A deep copy of this table. Every component with values must be cloneable and the queues empty.
+    pub fn fork(&self, components: &Components) -> Table {
+        let mut rows = BTreeMap::new();
+        for (id, row) in self.rows.iter() {
+            let mut copy = row.empty_like();
+            clone_column(&mut copy, row, components, *id);
+            rows.insert(*id, copy);
+        }
+
+        Self {
+            rows,
+            ticks: self.ticks.clone(),
+            ids: self.ids.clone(),
+            archetype: self.archetype.clone(),
+            edges: self.edges.clone(),
+            queue: Mutex::new(Queues::new()),
+            update: UnsafeCell::new(false),
+            modify: UnsafeCell::new(false),
+            num_entities: self.num_entities,
+        }
+    }
+
+    /// Copy the entities in this table into `state`, reusing its memory.
+    /// Every component with values must be cloneable.
+    pub fn save(&self, components: &Components, state: &mut TableState) {
+        if state.columns.len() != self.rows.len() 
+            || state.columns.iter().zip(self.rows.keys()).any(|(column, id)| column.id() != *id) 
+        {
+            state.clear();
+            state.columns = self.empty_columns();
+        }
+
+        for (column, (id, row)) in state.columns.iter_mut().zip(self.rows.iter()) {
+            clone_column(column, row, components, *id);
+        }
+        state.ids.clone_from(&self.ids);
+    }
+
+    /// Replace the entities in this table with the ones saved in `state`. Every loaded 
+    /// component is marked as changed at `tick`, and every component that is gone is 
+    /// recorded in `removals`.
+    pub fn load(&mut self, components: &Components, state: &TableState, tick: Tick, removals: &mut Removals) {
+        self.record_removals(&state.ids, tick, removals);
+        for (column, (id, row)) in state.columns.iter().zip(self.rows.iter_mut()) {
+            clone_column(row, column, components, *id);
+        }
+        self.ids.clone_from(&state.ids);
+        self.num_entities = self.ids.len();
+        for ticks in self.ticks.values_mut() {
+            ticks.clear();
+            ticks.resize(self.num_entities, tick);
+        }
+    }
+
+    /// Drop every entity in this table, recording them in `removals`.
+    pub fn clear(&mut self, tick: Tick, removals: &mut Removals) {
+        self.record_removals(&[], tick, removals);
+        for row in self.rows.values_mut() {
+            row.clear();
+        }
+        for ticks in self.ticks.values_mut() {
+            ticks.clear();
+        }
+        self.ids.clear();
+        self.num_entities = 0;
+    }
+
+    /// Record the components of every entity in this table that isn't in `keep` as removed.
+    fn record_removals(&self, keep: &[EntityId], tick: Tick, removals: &mut Removals) {
+        for id in self.ids.iter().filter(|id| !keep.contains(id)) {
+            for cmp in self.archetype.ids() {
+                removals.component(*cmp, *id, tick);
+            }
+        }
+    }
+
+    pub fn register_columns(&self, components: &mut Components) {
+        for row in self.rows.values() {
+            components.register_column(row);
+        }
+    }
+
+    pub fn contains(&self, ids: &[ComponentId]) -> bool {
         for id in ids.iter() {
             if !self.rows.contains_key(id) { return false }
         }
@@ -62,10 +261,33 @@ impl Table {
         if let Some(row) = self.rows.get(&C::__internal_id()) {
             return Some(row.iter_as::<C>())
         } else {
-            panic!("Attempted to collect component from archetype in which it does not exist")
+            panic!("Attempted to collect {} from an archetype in which it does not exist", std::any::type_name::<C>())
         }
     }
 
+    pub fn collect_ids(&self) -> Option<AnonIter<EntityId>> {
+        if !self.is_empty() {
+            Some(AnonIter {
+                ptr: self.ids.as_ptr() as *mut EntityId,
+                curr: 0,
+                len: self.num_entities,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// The change ticks of `C`, in the same order as `collect::<C>`.
+    pub fn collect_ticks<C: Component>(&self) -> Option<AnonIter<Tick>> {
+        if self.is_empty() { return None }
+
+        self.ticks.get(&C::__internal_id()).map(|ticks| AnonIter {
+            ptr: ticks.as_ptr() as *mut Tick,
+            curr: 0,
+            len: self.num_entities,
+        })
+    }
+
     pub fn collect_indices(&self, table: usize) -> Option<EntityIndexIter> {
         if !self.is_empty() {
             Some(EntityIndexIter {
@@ -79,12 +301,19 @@ impl Table {
     }
 
     pub fn spawn(&self, mut entity: Entity) {
+        debug_assert!(entity.archetype == self.archetype, "Spawned an entity into a table with a different archetype");
+
         let mut queue = self.queue.lock().unwrap();
         queue.spawn.push(entity);
         unsafe { *self.update.get() = true }
     }
 
     pub fn spawn_group(&self, mut entities: Vec<Entity>) {
+        debug_assert!(
+            entities.iter().all(|entity| entity.archetype == self.archetype), 
+            "Spawned an entity into a table with a different archetype"
+        );
+
         let mut queue = self.queue.lock().unwrap();
         queue.spawn.append(&mut entities);
         unsafe { *self.update.get() = true }
@@ -96,54 +325,143 @@ impl Table {
         unsafe { *self.update.get() = true }
     }
 
-    pub fn modify_group(&self, col: Column, mut insert: Vec<Anon>, mut remove: Vec<ComponentId>) {
+    /// The last `C` queued to be inserted into the entity in `col`, if any.
+    pub fn queued_mut<C: Component>(&mut self, col: Column) -> Option<&mut C> {
+        let (insert, _) = self.queue.get_mut().unwrap().modify.get_mut(&col)?;
+        insert.iter().rev().find(|anon| anon.id() == C::__internal_id()).map(|anon| anon.downcast_mut::<C>())
+    }
+
+    pub fn modify_group(&self, col: Column, insert: Vec<Anon>, remove: Vec<ComponentId>) {
         let mut queue = self.queue.lock().unwrap();
-        if let Some((ins, rem)) = queue.modify.get_mut(&col) {
-            if !insert.is_empty() { ins.append(&mut insert) }
-            if !remove.is_empty() { rem.append(&mut remove) }
-        } else {
-            queue.modify.insert(col, (insert, remove));
-        }
+        merge_modify(queue.modify.entry(col).or_default(), insert, remove);
         unsafe { *self.modify.get() = true; }
     }
 
-    pub fn process_modify(&mut self, entities: &mut Vec<Entity>) {
-        let mut queue = self.queue.lock().unwrap();
-        queue.destroy.sort();
-        queue.destroy.dedup();
-        while let Some((col, (mut ins, mut rem))) = queue.modify.pop() {
-            if queue.destroy.contains(&DestroyType::Drop(col)) {
+    /// Queue an `OnRemove` event for every hooked component that the 
+    /// queued destroys and modifies are about to remove.
+    pub fn pending_removals(&self, components: &Components, events: &mut Vec<HookEvent>) {
+        let queue = self.queue.lock().unwrap();
+
+        for destroy in queue.destroy.iter() {
+            if let DestroyType::Drop(col) = destroy {
+                for id in self.archetype.ids() {
+                    if components.has_hook(*id, HookKind::OnRemove) {
+                        events.push(HookEvent::new(HookKind::OnRemove, *id, self.ids[*col]));
+                    }
+                }
+            }
+        }
+
+        for (col, (insert, remove)) in queue.modify.iter() {
+            if queue.destroy.contains(&DestroyType::Drop(*col)) {
                 continue;
             }
 
-            let mut entity = self.entity_at(col);
-            
-            while let Some(rem) = rem.pop() {
-                entity.remove(rem);
+            for id in remove.iter() {
+                if self.has(*id) 
+                    && !insert.iter().any(|anon| anon.id() == *id) 
+                    && components.has_hook(*id, HookKind::OnRemove) 
+                {
+                    events.push(HookEvent::new(HookKind::OnRemove, *id, self.ids[*col]));
+                }
             }
+        }
+    }
 
-            while let Some(ins) = ins.pop() {
-                entity.insert_anon(ins);
+    /// The ids of every entity queued to be destroyed.
+    pub fn pending_destroys(&self, out: &mut Vec<EntityId>) {
+        let queue = self.queue.lock().unwrap();
+        for destroy in queue.destroy.iter() {
+            if let DestroyType::Drop(col) = destroy {
+                out.push(self.ids[*col]);
             }
+        }
+    }
 
-            queue.destroy.push(DestroyType::NoDrop(col));
+    /// Take every queued modify out of this table, skipping entities
+    /// that are also being destroyed this flush.
+    pub fn drain_modify(&mut self, table: TableIndex, modifies: &mut Vec<Modify>) {
+        let queue = self.queue.get_mut().unwrap();
+        while let Some((col, (insert, remove))) = queue.modify.pop() {
+            if queue.destroy.contains(&DestroyType::Drop(col)) {
+                continue;
+            }
 
-            entity.hash();
-            entities.push(entity);
+            modifies.push(Modify { table, col, insert, remove });
         }
 
-        unsafe { *self.modify.get() = false; }
+        *self.modify.get_mut() = false;
     }
 
-    pub fn process_queues(&mut self) {
-        let mut queue = self.queue.lock().unwrap();
+    /// Drop the component at `col` and replace it with `anon`, 
+    /// for inserts that don't change the archetype.
+    pub fn replace(&mut self, col: Column, anon: Anon, tick: Tick) {
+        if let Some(row) = self.rows.get_mut(&anon.id()) {
+            self.ticks.get_mut(&anon.id()).unwrap()[col] = tick;
+            row.replace(col, anon);
+        } else {
+            panic!("Attempted to replace a component that does not exist in this table")
+        }
+    }
+
+    /// Move the entity at `col` into `dst`, copying every column the two tables share, 
+    /// dropping the ones `dst` does not have, and pushing `insert` into the rest. 
+    /// The slot in this table is queued for removal without dropping.
+    /// Returns the id of the entity and the column it now occupies in `dst`.
+    /// Inserted components are marked as changed at `tick` and removed ones are recorded.
+    pub fn move_to(&mut self, col: Column, dst: &mut Table, insert: Vec<Anon>, tick: Tick, removals: &mut Removals) -> (EntityId, Column) {
+        for (id, row) in self.rows.iter_mut() {
+            match dst.rows.get_mut(id) {
+                Some(to) if !insert.iter().any(|anon| anon.id() == *id) => {
+                    to.push_from(row, col);
+                    dst.ticks.get_mut(id).unwrap().push(self.ticks[id][col]);
+                }
+                // removed or overwritten, so the old value is dropped here.
+                Some(_) => row.drop_at(col),
+                None => {
+                    row.drop_at(col);
+                    removals.component(*id, self.ids[col], tick);
+                }
+            }
+        }
+
+        for anon in insert {
+            if let Some(to) = dst.rows.get_mut(&anon.id()) {
+                dst.ticks.get_mut(&anon.id()).unwrap().push(tick);
+                to.push(anon);
+            } else {
+                panic!("Destination table is missing an inserted component")
+            }
+        }
+
+        let id = self.ids[col];
+        dst.ids.push(id);
+        dst.num_entities += 1;
+
+        self.queue.get_mut().unwrap().destroy.push(DestroyType::NoDrop(col));
+        *self.update.get_mut() = true;
+
+        (id, dst.num_entities - 1)
+    }
+
+    /// Apply queued spawns and destroys, keeping `entities` pointed at the 
+    /// right columns. The ids of spawned entities are pushed to `spawned`,
+    /// and destroyed entities are recorded in `removals`.
+    pub fn process_queues(&mut self, table: TableIndex, entities: &mut Entities, spawned: &mut Vec<EntityId>, tick: Tick, removals: &mut Removals) {
+        let queue = self.queue.get_mut().unwrap();
         
-        queue.destroy.sort();
-        queue.destroy.dedup();
+        // destroys are applied back-to-front so swaps never move 
+        // an entity that is waiting to be destroyed.
+        queue.destroy.sort_by_key(|destroy| destroy.col());
+        queue.destroy.dedup_by_key(|destroy| destroy.col());
 
         // perform all spawns
         while let Some(mut entity) = queue.spawn.pop() {
+            entities.set(entity.id, EntityIndex { table, col: self.ids.len() });
+            spawned.push(entity.id);
+            self.ids.push(entity.id);
             self.num_entities += 1;
+            self.ticks.values_mut().for_each(|ticks| ticks.push(tick));
             while let Some(anon) = entity.pop() {
                 if let Some(row) = self.rows.get_mut(&anon.id()) {
                     row.push(anon);
@@ -153,6 +471,7 @@ impl Table {
 
         // perform all destroys
         while let Some(destroy) = queue.destroy.pop() {
+            let col = destroy.col();
             self.num_entities -= 1;
             for (_, row) in self.rows.iter_mut() {
                 match destroy {
@@ -160,20 +479,87 @@ impl Table {
                     DestroyType::NoDrop(col) => row.destroy_nodrop(col),
                 }
             }
+            self.ticks.values_mut().for_each(|ticks| { ticks.swap_remove(col); });
+
+            // entities that moved out already have their new location.
+            if let DestroyType::Drop(_) = destroy {
+                for cmp in self.archetype.ids() {
+                    removals.component(*cmp, self.ids[col], tick);
+                }
+                removals.despawn(self.ids[col], tick);
+                entities.free(self.ids[col]);
+            }
+
+            // the last entity was swapped into this column.
+            self.ids.swap_remove(col);
+            if col < self.ids.len() {
+                entities.set(self.ids[col], EntityIndex { table, col });
+            }
         }
 
-        unsafe { *self.update.get() = false; }
+        *self.update.get_mut() = false;
     }
+}
 
-    fn entity_at(&self, col: Column) -> Entity {
-        let mut entity = Entity::new();
-        for (_, row) in self.rows.iter() {
-            entity.insert_anon(row.index(col));
+/// The entities of a table, copied by `Table::save`.
+#[derive(Default)]
+pub struct TableState {
+    ids: Vec<EntityId>,
+    columns: Vec<AnonVec>,
+}
+
+impl TableState {
+    fn clear(&mut self) {
+        self.columns.iter_mut().for_each(|column| column.clear());
+        self.ids.clear();
+    }
+}
+
+impl Drop for TableState {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+/// Replace `dst` with clones of `src`. Only a column with values in it needs a
+/// clone fn, so empty tables of components that aren't cloneable can be copied.
+fn clone_column(dst: &mut AnonVec, src: &AnonVec, components: &Components, id: ComponentId) {
+    if src.len() == 0 {
+        dst.clear();
+        return;
+    }
+    match components.get(id).and_then(|info| info.clone_fn()) {
+        Some(clone) => dst.clone_from_with(src, clone),
+        None => panic!("Attempted to clone {}, but it was not registered as cloneable!", components.name(id)),
+    }
+}
+
+/// Cached neighbours in the archetype graph. 
+#[derive(Clone)]
+pub struct Edges {
+    /// The table an entity moves to when a component is added.
+    pub add: HashMap<ComponentId, TableIndex>,
+    /// The table an entity moves to when a component is removed.
+    pub remove: HashMap<ComponentId, TableIndex>,
+}
+
+impl Edges {
+    pub fn new() -> Self {
+        Self {
+            add: HashMap::new(),
+            remove: HashMap::new(),
         }
-        entity
     }
 }
 
+/// A queued insert/remove for the entity at `col` in `table`.
+pub struct Modify {
+    pub table: TableIndex,
+    pub col: Column,
+    pub insert: Vec<Anon>,
+    pub remove: Vec<ComponentId>,
+}
+
 struct Queues {
     spawn: Vec<Entity>,
     destroy: Vec<DestroyType>,
@@ -196,5 +582,14 @@ pub enum DestroyType {
     NoDrop(Column),
 }
 
+impl DestroyType {
+    pub fn col(&self) -> Column {
+        match *self {
+            DestroyType::Drop(col) => col,
+            DestroyType::NoDrop(col) => col,
+        }
+    }
+}
+
 unsafe impl Sync for Table { }
 unsafe impl Send for Table { }
\ No newline at end of file
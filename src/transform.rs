@@ -0,0 +1,400 @@
+use std::collections::HashSet;
+use std::ops::{Add, Mul, Sub};
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::builder::{EngineBuilder, Plugin};
+use crate::commands::Commands;
+use crate::components::impl_component;
+use crate::engine::Engine;
+use crate::entity::EntityId;
+use crate::hierarchy::{Children, Parent};
+use crate::query::{Mut, Query, Ref};
+use crate::reflect::Reflect;
+use crate::checksum::macros::impl_stable_hash;
+use crate::systems::Stage;
+use crate::changes::{Tick, Ticks};
+
+#[derive(Copy, Clone, PartialEq, Debug, Default, Serialize, Deserialize, Reflect)]
+pub struct Vec3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Vec3 {
+    pub const ZERO: Vec3 = Vec3::new(0.0, 0.0, 0.0);
+    pub const ONE: Vec3 = Vec3::new(1.0, 1.0, 1.0);
+
+    pub const fn new(x: f32, y: f32, z: f32) -> Self {
+        Self { x, y, z }
+    }
+
+    pub fn dot(self, other: Vec3) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross(self, other: Vec3) -> Vec3 {
+        Vec3::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x,
+        )
+    }
+}
+
+impl Add for Vec3 {
+    type Output = Vec3;
+
+    fn add(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Vec3;
+
+    fn sub(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+/// Component-wise multiplication.
+impl Mul for Vec3 {
+    type Output = Vec3;
+
+    fn mul(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x * other.x, self.y * other.y, self.z * other.z)
+    }
+}
+
+impl Mul<f32> for Vec3 {
+    type Output = Vec3;
+
+    fn mul(self, s: f32) -> Vec3 {
+        Vec3::new(self.x * s, self.y * s, self.z * s)
+    }
+}
+
+impl_stable_hash!(Vec3 { x, y, z });
+
+/// A unit quaternion representing a rotation.
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize, Reflect)]
+pub struct Quat {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Quat {
+    pub const IDENTITY: Quat = Quat { x: 0.0, y: 0.0, z: 0.0, w: 1.0 };
+
+    /// A rotation of `angle` radians around the normalized `axis`.
+    pub fn from_axis_angle(axis: Vec3, angle: f32) -> Self {
+        let (s, c) = (angle * 0.5).sin_cos();
+        Self { x: axis.x * s, y: axis.y * s, z: axis.z * s, w: c }
+    }
+
+    pub fn rotate(self, v: Vec3) -> Vec3 {
+        // v' = v + 2w(q x v) + 2(q x (q x v))
+        let q = Vec3::new(self.x, self.y, self.z);
+        let t = q.cross(v) * 2.0;
+        v + t * self.w + q.cross(t)
+    }
+}
+
+impl_stable_hash!(Quat { x, y, z, w });
+
+impl Default for Quat {
+    fn default() -> Self {
+        Quat::IDENTITY
+    }
+}
+
+/// Hamilton product, applying `other` first and then `self`.
+impl Mul for Quat {
+    type Output = Quat;
+
+    fn mul(self, o: Quat) -> Quat {
+        Quat {
+            x: self.w * o.x + self.x * o.w + self.y * o.z - self.z * o.y,
+            y: self.w * o.y - self.x * o.z + self.y * o.w + self.z * o.x,
+            z: self.w * o.z + self.x * o.y - self.y * o.x + self.z * o.w,
+            w: self.w * o.w - self.x * o.x - self.y * o.y - self.z * o.z,
+        }
+    }
+}
+
+/// Position, rotation and scale of an entity relative to its parent.
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize, Reflect)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl_component!(Transform);
+impl_stable_hash!(Transform { translation, rotation, scale });
+
+impl Transform {
+    pub const IDENTITY: Transform = Transform {
+        translation: Vec3::ZERO,
+        rotation: Quat::IDENTITY,
+        scale: Vec3::ONE,
+    };
+
+    pub fn from_translation(translation: Vec3) -> Self {
+        Self { translation, ..Self::IDENTITY }
+    }
+
+    /// `child` expressed in the space this transform is in.
+    pub fn mul_transform(&self, child: &Transform) -> Transform {
+        Transform {
+            translation: self.translation + self.rotation.rotate(self.scale * child.translation),
+            rotation: self.rotation * child.rotation,
+            scale: self.scale * child.scale,
+        }
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::IDENTITY
+    }
+}
+
+/// The transform of an entity relative to the world, written by `propagate_transforms`.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct GlobalTransform {
+    global: Transform,
+    /// the tick this was computed at and the parent it was computed from
+    computed: Tick,
+    parent: Option<EntityId>,
+}
+
+impl_component!(GlobalTransform);
+
+impl GlobalTransform {
+    pub fn get(&self) -> &Transform {
+        &self.global
+    }
+
+    pub fn translation(&self) -> Vec3 {
+        self.global.translation
+    }
+}
+
+/// Keeps every `GlobalTransform` up to date with the `Transform`s in the hierarchy.
+pub struct TransformPlugin;
+
+impl Plugin for TransformPlugin {
+    fn build(&self, builder: &mut EngineBuilder) {
+        builder
+            .register_serializable::<Transform>()
+            .register_reflect::<Transform>()
+            .register_clone::<Transform>()
+            .register_clone::<GlobalTransform>()
+            .register_hash::<Transform>()
+            .on_add::<Transform>(insert_global_transform)
+            .load_system(propagate_transforms, Stage::Late);
+    }
+}
+
+/// Give every entity with a `Transform` a `GlobalTransform`.
+fn insert_global_transform(engine: &mut Engine, entity: EntityId, commands: &mut Commands) {
+    if engine.get::<GlobalTransform>(entity).is_none() {
+        commands.insert(entity, GlobalTransform::default());
+    }
+}
+
+/// Recompute the subtrees whose root's `Transform` or parent changed since the 
+/// last run, in parallel. Unchanged subtrees are skipped.
+pub fn propagate_transforms(
+    ticks: Ticks,
+    transforms: Query<(Ref<Transform>, Mut<GlobalTransform>)>,
+    parents: Query<(Ref<Parent>,)>,
+    children: Query<(Ref<Children>,)>,
+) {
+    let now = ticks.advance();
+    let parent_of = |id: EntityId| parents.get(id).map(|(parent, _)| parent.get());
+
+    let mut dirty = HashSet::new();
+    for (local, global, id) in transforms.iter() {
+        if local.last_changed() > global.computed || parent_of(id) != global.parent {
+            dirty.insert(id);
+        }
+    }
+
+    // a dirty entity under another one is recomputed with its ancestor's subtree,
+    // unless an entity without a `Transform` between them stops the propagation.
+    let tops: Vec<EntityId> = dirty.iter()
+        .filter(|id| {
+            let mut ancestor = parent_of(**id);
+            while let Some(parent) = ancestor {
+                if dirty.contains(&parent) {
+                    return false;
+                }
+                if transforms.get(parent).is_none() {
+                    break;
+                }
+                ancestor = parent_of(parent);
+            }
+            true
+        })
+        .copied()
+        .collect();
+
+    // the subtrees are disjoint, so each one can be written from its own thread.
+    tops.par_iter().for_each(|top| {
+        let parent = parent_of(*top).map(|parent| {
+            (parent, transforms.get(parent).map(|(_, global, _)| global.global))
+        });
+        propagate(*top, parent, now, &transforms, &children);
+    });
+}
+
+/// Recompute `entity` and everything below it. A parent without a
+/// `Transform` leaves its children in world space.
+fn propagate(
+    entity: EntityId,
+    parent: Option<(EntityId, Option<Transform>)>,
+    now: Tick,
+    transforms: &Query<(Ref<Transform>, Mut<GlobalTransform>)>,
+    children: &Query<(Ref<Children>,)>,
+) {
+    let (local, mut global, _) = match transforms.get(entity) {
+        Some(item) => item,
+        None => return,
+    };
+
+    global.global = match parent {
+        Some((_, Some(p))) => p.mul_transform(&local),
+        _ => *local,
+    };
+    global.computed = now;
+    global.parent = parent.map(|(id, _)| id);
+
+    if let Some((kids, _)) = children.get(entity) {
+        let current = global.global;
+        for child in kids.iter() {
+            propagate(*child, Some((entity, Some(current))), now, transforms, children);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn engine() -> Engine {
+        let mut builder = EngineBuilder::new();
+        builder.load_plugin(TransformPlugin);
+        builder.build()
+    }
+
+    fn at(x: f32) -> Transform {
+        Transform::from_translation(Vec3::new(x, 0.0, 0.0))
+    }
+
+    fn x(engine: &Engine, entity: EntityId) -> f32 {
+        engine.get::<GlobalTransform>(entity).unwrap().translation().x
+    }
+
+    #[test]
+    fn root_without_parent() {
+        let mut engine = engine();
+        let root = engine.spawn(|e| e.insert(at(1.0)));
+        engine.execute_systems();
+        assert_eq!(x(&engine, root), 1.0);
+
+        engine.get_mut::<Transform>(root).unwrap().translation.x = 2.0;
+        engine.execute_systems();
+        assert_eq!(x(&engine, root), 2.0);
+    }
+
+    #[test]
+    fn nested_chain() {
+        let mut engine = engine();
+        let root = engine.spawn(|e| e.insert(at(1.0)));
+        let middle = engine.spawn(|e| e.insert(at(10.0)));
+        let leaf = engine.spawn(|e| e.insert(at(100.0)));
+        let mut commands = engine.commands();
+        commands.entity(middle).set_parent(root);
+        commands.entity(leaf).set_parent(middle);
+        drop(commands);
+        engine.execute_systems();
+        assert_eq!(x(&engine, middle), 11.0);
+        assert_eq!(x(&engine, leaf), 111.0);
+
+        // only the changed root's subtree is recomputed.
+        let other = engine.spawn(|e| e.insert(at(5.0)));
+        engine.execute_systems();
+        let computed = engine.last_changed::<GlobalTransform>(leaf);
+        engine.get_mut::<Transform>(other).unwrap().translation.x = 6.0;
+        engine.execute_systems();
+        assert_eq!(engine.last_changed::<GlobalTransform>(leaf), computed);
+        assert_eq!(x(&engine, other), 6.0);
+
+        engine.get_mut::<Transform>(root).unwrap().translation.x = 2.0;
+        engine.execute_systems();
+        assert_eq!(x(&engine, middle), 12.0);
+        assert_eq!(x(&engine, leaf), 112.0);
+    }
+
+    #[test]
+    fn reparented_child() {
+        let mut engine = engine();
+        let a = engine.spawn(|e| e.insert(at(1.0)));
+        let b = engine.spawn(|e| e.insert(at(2.0)));
+        let child = engine.spawn(|e| e.insert(at(10.0)));
+        engine.commands().entity(child).set_parent(a);
+        engine.execute_systems();
+        assert_eq!(x(&engine, child), 11.0);
+
+        engine.commands().entity(child).set_parent(b);
+        engine.execute_systems();
+        assert_eq!(x(&engine, child), 12.0);
+
+        engine.commands().entity(child).remove_parent();
+        engine.execute_systems();
+        assert_eq!(x(&engine, child), 10.0);
+    }
+
+    #[test]
+    fn despawned_parent() {
+        let mut engine = engine();
+        let parent = engine.spawn(|e| e.insert(at(1.0)));
+        let child = engine.spawn(|e| e.insert(at(10.0)));
+        engine.commands().entity(child).set_parent(parent);
+        engine.execute_systems();
+        assert_eq!(x(&engine, child), 11.0);
+
+        engine.despawn(parent);
+        engine.execute_systems();
+        assert!(engine.get::<Parent>(child).is_none());
+        assert_eq!(x(&engine, child), 10.0);
+    }
+
+    #[test]
+    fn parent_without_transform() {
+        let mut engine = engine();
+        let root = engine.spawn(|e| e.insert(at(1.0)));
+        let middle = engine.spawn(|_| {});
+        let leaf = engine.spawn(|e| e.insert(at(10.0)));
+        let mut commands = engine.commands();
+        commands.entity(middle).set_parent(root);
+        commands.entity(leaf).set_parent(middle);
+        drop(commands);
+        engine.execute_systems();
+        assert_eq!(x(&engine, leaf), 10.0);
+
+        // the leaf is still recomputed when its dirty ancestor is above the gap.
+        engine.get_mut::<Transform>(root).unwrap().translation.x = 2.0;
+        engine.get_mut::<Transform>(leaf).unwrap().translation.x = 20.0;
+        engine.execute_systems();
+        assert_eq!(x(&engine, root), 2.0);
+        assert_eq!(x(&engine, leaf), 20.0);
+    }
+}